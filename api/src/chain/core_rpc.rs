@@ -0,0 +1,275 @@
+// api/src/chain/core_rpc.rs
+use super::{ChainBackend, MempoolAcceptResult, PackageTxOutcome, Utxo};
+use crate::config::RpcConfig;
+use crate::error::{WalletError, WalletResult};
+use async_trait::async_trait;
+use bitcoin::{
+    consensus::encode::serialize_hex, Address, FeeRate, OutPoint, ScriptBuf, Transaction, TxOut,
+    Txid,
+};
+use bitcoincore_rpc::json::ScanTxOutRequest;
+use bitcoincore_rpc::{jsonrpc, Auth, Client as RpcClient, RpcApi};
+use serde_json::{json, Value};
+use std::{collections::HashMap, sync::Arc};
+use tokio::sync::Mutex;
+
+/// JSON-RPC error code for an unknown method, e.g. `submitpackage` before Core 26.
+const RPC_METHOD_NOT_FOUND: i32 = -32601;
+
+/// Connects to the configured Bitcoin Core node, optionally scoped to one of its wallets.
+pub fn rpc_client(rpc: &RpcConfig, wallet: Option<&str>) -> WalletResult<RpcClient> {
+    let url = match wallet {
+        Some(name) => format!("{}/wallet/{}", rpc.url, name),
+        None => rpc.url.clone(),
+    };
+
+    RpcClient::new(
+        &url,
+        Auth::UserPass(rpc.user.clone(), rpc.password.expose().clone()),
+    )
+    .map_err(|e| WalletError::BitcoinError(e.to_string()))
+}
+
+/// Runs a blocking RPC call on tokio's blocking pool.
+///
+/// `bitcoincore-rpc` is synchronous; calling it directly from an async
+/// handler would stall a runtime worker for the whole round trip.
+pub async fn blocking_rpc<T, F>(client: &Arc<RpcClient>, f: F) -> WalletResult<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&RpcClient) -> WalletResult<T> + Send + 'static,
+{
+    let client = client.clone();
+    tokio::task::spawn_blocking(move || f(&client))
+        .await
+        .map_err(|e| WalletError::NetworkError(format!("RPC task failed: {}", e)))?
+}
+
+/// Turns a failed `sendrawtransaction`-style call into a typed reject error.
+///
+/// Only the node's own message is kept; transport errors are reported
+/// generically so connection details never reach the client.
+fn reject_error(e: bitcoincore_rpc::Error) -> WalletError {
+    match e {
+        bitcoincore_rpc::Error::JsonRpc(jsonrpc::Error::Rpc(rpc_error)) => {
+            WalletError::from_reject_reason(&rpc_error.message)
+        }
+        other => {
+            tracing::error!("Broadcast RPC call failed: {}", other);
+            WalletError::NetworkError("Failed to reach the Bitcoin node".to_string())
+        }
+    }
+}
+
+/// Chain access through a Bitcoin Core node's JSON-RPC interface.
+pub struct CoreRpcBackend {
+    client: Arc<RpcClient>,
+    /// Core runs one `scantxoutset` at a time and fails any other started
+    /// meanwhile, so concurrent requests take turns instead.
+    scan_lock: Mutex<()>,
+}
+
+impl CoreRpcBackend {
+    pub fn new(client: Arc<RpcClient>) -> Self {
+        Self {
+            client,
+            scan_lock: Mutex::new(()),
+        }
+    }
+}
+
+#[async_trait]
+impl ChainBackend for CoreRpcBackend {
+    fn name(&self) -> &'static str {
+        "core"
+    }
+
+    /// Uses `scantxoutset`, so only confirmed outputs are reported; outputs
+    /// still in the mempool are missing until they confirm.
+    async fn get_utxos(&self, address: &Address) -> WalletResult<Vec<Utxo>> {
+        let mut utxos = self.get_utxos_batch(std::slice::from_ref(address)).await?;
+        Ok(utxos.pop().unwrap_or_default())
+    }
+
+    /// Looks every address up in a single `scantxoutset`, which walks the
+    /// whole UTXO set once however many descriptors it is given. Confirmed
+    /// outputs only, as for [`CoreRpcBackend::get_utxos`].
+    async fn get_utxos_batch(&self, addresses: &[Address]) -> WalletResult<Vec<Vec<Utxo>>> {
+        if addresses.is_empty() {
+            return Ok(Vec::new());
+        }
+        let requests: Vec<ScanTxOutRequest> = addresses
+            .iter()
+            .map(|address| ScanTxOutRequest::Single(format!("addr({})", address)))
+            .collect();
+
+        let _scan = self.scan_lock.lock().await;
+        let result = blocking_rpc(&self.client, move |client| {
+            client
+                .scan_tx_out_set_blocking(&requests)
+                .map_err(|e| WalletError::BitcoinError(format!("Failed to scan UTXO set: {}", e)))
+        })
+        .await?;
+
+        let positions: HashMap<ScriptBuf, usize> = addresses
+            .iter()
+            .enumerate()
+            .map(|(position, address)| (address.script_pubkey(), position))
+            .collect();
+        let mut utxos = vec![Vec::new(); addresses.len()];
+        for unspent in result.unspents {
+            if let Some(&position) = positions.get(&unspent.script_pub_key) {
+                utxos[position].push(Utxo {
+                    outpoint: OutPoint::new(unspent.txid, unspent.vout),
+                    value: unspent.amount,
+                    height: Some(unspent.height as u32),
+                });
+            }
+        }
+        Ok(utxos)
+    }
+
+    async fn get_transaction(&self, txid: &Txid) -> WalletResult<Transaction> {
+        let txid = *txid;
+        blocking_rpc(&self.client, move |client| {
+            client.get_raw_transaction(&txid, None).map_err(|e| {
+                WalletError::BitcoinError(format!("Failed to get raw transaction: {}", e))
+            })
+        })
+        .await
+    }
+
+    async fn get_tx_out(&self, outpoint: &OutPoint) -> WalletResult<Option<TxOut>> {
+        let outpoint = *outpoint;
+        let tx_out = blocking_rpc(&self.client, move |client| {
+            client
+                .get_tx_out(&outpoint.txid, outpoint.vout, Some(true))
+                .map_err(|e| WalletError::BitcoinError(format!("Failed to get tx_out: {}", e)))
+        })
+        .await?;
+
+        Ok(tx_out.map(|out| TxOut {
+            value: out.value,
+            script_pubkey: ScriptBuf::from(out.script_pub_key.hex),
+        }))
+    }
+
+    async fn broadcast(&self, tx: &Transaction) -> WalletResult<Txid> {
+        let tx = tx.clone();
+        blocking_rpc(&self.client, move |client| {
+            client.send_raw_transaction(&tx).map_err(reject_error)
+        })
+        .await
+    }
+
+    async fn submit_package(
+        &self,
+        txs: &[Transaction],
+    ) -> WalletResult<Option<Vec<PackageTxOutcome>>> {
+        let raw_txs: Vec<String> = txs.iter().map(serialize_hex).collect();
+        let result = blocking_rpc(&self.client, move |client| {
+            match client.call::<Value>("submitpackage", &[json!(raw_txs)]) {
+                Ok(result) => Ok(Some(Ok(result))),
+                Err(bitcoincore_rpc::Error::JsonRpc(jsonrpc::Error::Rpc(e)))
+                    if e.code == RPC_METHOD_NOT_FOUND =>
+                {
+                    Ok(None)
+                }
+                // The node refused the package as a whole (bad topology,
+                // package fee too low, ...): nothing reached the mempool.
+                Err(bitcoincore_rpc::Error::JsonRpc(jsonrpc::Error::Rpc(e))) => {
+                    Ok(Some(Err(e.message)))
+                }
+                Err(e) => Err(reject_error(e)),
+            }
+        })
+        .await?;
+
+        let result = match result {
+            None => return Ok(None),
+            Some(Ok(result)) => result,
+            Some(Err(reason)) => {
+                let error = WalletError::from_reject_reason(&reason).to_string();
+                return Ok(Some(
+                    txs.iter()
+                        .map(|tx| PackageTxOutcome {
+                            txid: tx.compute_txid(),
+                            error: Some(error.clone()),
+                        })
+                        .collect(),
+                ));
+            }
+        };
+
+        // `tx-results` is keyed by wtxid; match entries back to our txids.
+        let package_msg = result["package_msg"].as_str().unwrap_or("not accepted");
+        let outcomes = txs
+            .iter()
+            .map(|tx| {
+                let txid = tx.compute_txid();
+                let entry = result["tx-results"].as_object().and_then(|entries| {
+                    entries
+                        .values()
+                        .find(|entry| entry["txid"].as_str() == Some(txid.to_string().as_str()))
+                });
+                let error = match entry {
+                    Some(entry) => entry["error"].as_str(),
+                    None => Some(package_msg),
+                };
+                let error = error.map(|reason| WalletError::from_reject_reason(reason).to_string());
+                PackageTxOutcome { txid, error }
+            })
+            .collect();
+
+        Ok(Some(outcomes))
+    }
+
+    async fn test_mempool_accept(
+        &self,
+        txs: &[Transaction],
+    ) -> WalletResult<Vec<MempoolAcceptResult>> {
+        let txs = txs.to_vec();
+        let results = blocking_rpc(&self.client, move |client| {
+            let raw_txs: Vec<&Transaction> = txs.iter().collect();
+            client.test_mempool_accept(&raw_txs).map_err(reject_error)
+        })
+        .await?;
+
+        Ok(results
+            .into_iter()
+            .map(|result| MempoolAcceptResult {
+                txid: result.txid,
+                allowed: result.allowed,
+                reject_reason: result.reject_reason,
+                vsize: result.vsize,
+                fee: result.fees.map(|fees| fees.base),
+            })
+            .collect())
+    }
+
+    async fn estimate_fee(&self, conf_target: u16) -> WalletResult<Option<FeeRate>> {
+        let estimate = blocking_rpc(&self.client, move |client| {
+            client
+                .estimate_smart_fee(conf_target, None)
+                .map_err(|e| WalletError::BitcoinError(format!("Failed to estimate fee: {}", e)))
+        })
+        .await?;
+
+        // Core reports BTC/kvB; one kvB is four kwu. Round up, as the
+        // Esplora backend does, so the rate never drops below Core's.
+        Ok(estimate
+            .fee_rate
+            .map(|rate| FeeRate::from_sat_per_kwu(rate.to_sat().div_ceil(4))))
+    }
+
+    async fn tip_height(&self) -> WalletResult<u32> {
+        let height = blocking_rpc(&self.client, |client| {
+            client
+                .get_block_count()
+                .map_err(|e| WalletError::BitcoinError(format!("Failed to get block count: {}", e)))
+        })
+        .await?;
+
+        Ok(height as u32)
+    }
+}
@@ -0,0 +1,431 @@
+// api/src/chain/esplora.rs
+use super::{AddressTx, ChainBackend, MempoolAcceptResult, Utxo};
+use crate::error::{WalletError, WalletResult};
+use async_trait::async_trait;
+use bitcoin::{
+    consensus::{deserialize, encode::serialize_hex},
+    hashes::hex::FromHex,
+    Address, Amount, FeeRate, OutPoint, Transaction, TxOut, Txid,
+};
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
+
+/// Chain access through an Esplora-compatible REST API such as mempool.space.
+pub struct EsploraBackend {
+    client: Client,
+    base_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct EsploraStatus {
+    confirmed: bool,
+    block_height: Option<u32>,
+}
+
+impl EsploraStatus {
+    fn height(&self) -> Option<u32> {
+        if self.confirmed {
+            self.block_height
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct EsploraUtxo {
+    txid: Txid,
+    vout: u32,
+    value: u64,
+    status: EsploraStatus,
+}
+
+#[derive(Debug, Deserialize)]
+struct EsploraAddressStats {
+    tx_count: u64,
+    funded_txo_count: u64,
+    spent_txo_count: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct EsploraAddress {
+    chain_stats: EsploraAddressStats,
+    mempool_stats: EsploraAddressStats,
+}
+
+impl EsploraAddress {
+    /// Outputs still unspent once the mempool is taken into account.
+    fn unspent_count(&self) -> u64 {
+        (self.chain_stats.funded_txo_count + self.mempool_stats.funded_txo_count)
+            .saturating_sub(self.chain_stats.spent_txo_count + self.mempool_stats.spent_txo_count)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct EsploraTxOut {
+    scriptpubkey: String,
+    value: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct EsploraTxIn {
+    txid: Txid,
+    vout: u32,
+    /// `None` for coinbase inputs.
+    prevout: Option<EsploraTxOut>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EsploraTx {
+    txid: Txid,
+    vin: Vec<EsploraTxIn>,
+    vout: Vec<EsploraTxOut>,
+    fee: Option<u64>,
+    status: EsploraStatus,
+}
+
+#[derive(Debug, Deserialize)]
+struct EsploraOutspend {
+    spent: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct EsploraAcceptFees {
+    /// Fee in BTC, as reported by `testmempoolaccept`.
+    base: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct EsploraAcceptResult {
+    txid: Txid,
+    allowed: Option<bool>,
+    #[serde(rename = "reject-reason")]
+    reject_reason: Option<String>,
+    vsize: Option<u64>,
+    fees: Option<EsploraAcceptFees>,
+}
+
+impl EsploraBackend {
+    pub fn new(client: Client, base_url: String) -> Self {
+        Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    async fn get_text(&self, path: &str) -> WalletResult<Option<String>> {
+        let url = format!("{}{}", self.base_url, path);
+        tracing::debug!("Esplora GET {}", url);
+
+        let response = self
+            .client
+            .get(&url)
+            .send()
+            .await
+            .map_err(|e| WalletError::NetworkError(e.to_string()))?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(WalletError::NetworkError(format!(
+                "Esplora returned {} for {}",
+                response.status(),
+                path
+            )));
+        }
+
+        response
+            .text()
+            .await
+            .map(Some)
+            .map_err(|e| WalletError::NetworkError(e.to_string()))
+    }
+
+    async fn get_json<T: for<'de> Deserialize<'de>>(&self, path: &str) -> WalletResult<Option<T>> {
+        match self.get_text(path).await? {
+            Some(text) => serde_json::from_str(&text)
+                .map(Some)
+                .map_err(|e| WalletError::NetworkError(e.to_string())),
+            None => Ok(None),
+        }
+    }
+}
+
+impl EsploraBackend {
+    /// Full history of `address`: the mempool page, then every confirmed page.
+    ///
+    /// Esplora returns confirmed transactions 25 at a time, continued from
+    /// the last txid seen. The mempool list is capped at 50 entries and
+    /// cannot be paged further.
+    async fn fetch_history(&self, address: &Address) -> WalletResult<Vec<EsploraTx>> {
+        let mut txs: Vec<EsploraTx> = self
+            .get_json(&format!("/address/{}/txs/mempool", address))
+            .await?
+            .unwrap_or_default();
+
+        let mut last_seen: Option<Txid> = None;
+        loop {
+            let path = match last_seen {
+                Some(txid) => format!("/address/{}/txs/chain/{}", address, txid),
+                None => format!("/address/{}/txs/chain", address),
+            };
+            let page: Vec<EsploraTx> = self.get_json(&path).await?.unwrap_or_default();
+            match page.last() {
+                Some(tx) => last_seen = Some(tx.txid),
+                None => break,
+            }
+            txs.extend(page);
+        }
+
+        Ok(txs)
+    }
+}
+
+/// Outputs to `script` in `txs` that no transaction in `txs` spends. Any spend
+/// of the address's outputs is itself part of its history, so a complete
+/// history gives the complete UTXO set.
+fn unspent_from_history(script: &str, txs: &[EsploraTx]) -> Vec<Utxo> {
+    let spent: HashSet<OutPoint> = txs
+        .iter()
+        .flat_map(|tx| &tx.vin)
+        .map(|input| OutPoint::new(input.txid, input.vout))
+        .collect();
+
+    txs.iter()
+        .flat_map(|tx| {
+            tx.vout
+                .iter()
+                .enumerate()
+                .filter(|(_, out)| out.scriptpubkey == script)
+                .map(move |(vout, out)| Utxo {
+                    outpoint: OutPoint::new(tx.txid, vout as u32),
+                    value: Amount::from_sat(out.value),
+                    height: tx.status.height(),
+                })
+        })
+        .filter(|utxo| !spent.contains(&utxo.outpoint))
+        .collect()
+}
+
+/// Extracts the node message from an Esplora error body such as
+/// `sendrawtransaction RPC error: {"code":-26,"message":"min relay fee not met"}`.
+fn reject_message(body: &str) -> String {
+    body.find('{')
+        .and_then(|start| serde_json::from_str::<serde_json::Value>(&body[start..]).ok())
+        .and_then(|value| value["message"].as_str().map(str::to_string))
+        .unwrap_or_else(|| body.to_string())
+}
+
+#[async_trait]
+impl ChainBackend for EsploraBackend {
+    fn name(&self) -> &'static str {
+        "esplora"
+    }
+
+    /// Uses `/utxo` when its result matches the address stats. Servers cap
+    /// that list (or reject the request) for busy addresses; the UTXO set is
+    /// then rebuilt from the paginated history.
+    async fn get_utxos(&self, address: &Address) -> WalletResult<Vec<Utxo>> {
+        let expected = self
+            .get_json::<EsploraAddress>(&format!("/address/{}", address))
+            .await?
+            .map_or(0, |stats| stats.unspent_count());
+
+        match self
+            .get_json::<Vec<EsploraUtxo>>(&format!("/address/{}/utxo", address))
+            .await
+        {
+            Ok(utxos) if utxos.as_ref().map_or(0, Vec::len) as u64 == expected => {
+                return Ok(utxos
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|utxo| Utxo {
+                        outpoint: OutPoint::new(utxo.txid, utxo.vout),
+                        value: Amount::from_sat(utxo.value),
+                        height: utxo.status.height(),
+                    })
+                    .collect())
+            }
+            Ok(_) => tracing::warn!(
+                "Esplora UTXO list for {} is incomplete, rebuilding it from history",
+                address
+            ),
+            Err(e) => tracing::warn!(
+                "Esplora UTXO list for {} failed ({}), rebuilding it from history",
+                address,
+                e
+            ),
+        }
+
+        let script = address.script_pubkey().to_hex_string();
+        let txs = self.fetch_history(address).await?;
+        Ok(unspent_from_history(&script, &txs))
+    }
+
+    async fn get_history(&self, address: &Address) -> WalletResult<Vec<AddressTx>> {
+        let script = address.script_pubkey().to_hex_string();
+        let paying = |out: &&EsploraTxOut| out.scriptpubkey == script;
+
+        Ok(self
+            .fetch_history(address)
+            .await?
+            .into_iter()
+            .map(|tx| AddressTx {
+                txid: tx.txid,
+                height: tx.status.height(),
+                fee: tx.fee.map(Amount::from_sat),
+                received: Amount::from_sat(
+                    tx.vout.iter().filter(paying).map(|out| out.value).sum(),
+                ),
+                sent: Amount::from_sat(
+                    tx.vin
+                        .iter()
+                        .filter_map(|input| input.prevout.as_ref())
+                        .filter(paying)
+                        .map(|out| out.value)
+                        .sum(),
+                ),
+            })
+            .collect())
+    }
+
+    async fn has_history(&self, address: &Address) -> WalletResult<bool> {
+        let stats: Option<EsploraAddress> = self.get_json(&format!("/address/{}", address)).await?;
+        Ok(
+            stats
+                .is_some_and(|stats| stats.chain_stats.tx_count + stats.mempool_stats.tx_count > 0),
+        )
+    }
+
+    async fn get_transaction(&self, txid: &Txid) -> WalletResult<Transaction> {
+        let tx_hex = self
+            .get_text(&format!("/tx/{}/hex", txid))
+            .await?
+            .ok_or_else(|| WalletError::BitcoinError(format!("Transaction {} not found", txid)))?;
+
+        let tx_bytes = Vec::<u8>::from_hex(tx_hex.trim())
+            .map_err(|e| WalletError::BitcoinError(format!("Invalid hex: {}", e)))?;
+        deserialize(&tx_bytes)
+            .map_err(|e| WalletError::BitcoinError(format!("Deserialization failed: {}", e)))
+    }
+
+    async fn get_tx_out(&self, outpoint: &OutPoint) -> WalletResult<Option<TxOut>> {
+        let outspend: Option<EsploraOutspend> = self
+            .get_json(&format!("/tx/{}/outspend/{}", outpoint.txid, outpoint.vout))
+            .await?;
+        if outspend.is_none_or(|o| o.spent) {
+            return Ok(None);
+        }
+
+        let tx = self.get_transaction(&outpoint.txid).await?;
+        Ok(tx.output.get(outpoint.vout as usize).cloned())
+    }
+
+    async fn broadcast(&self, tx: &Transaction) -> WalletResult<Txid> {
+        let url = format!("{}/tx", self.base_url);
+        let response = self
+            .client
+            .post(&url)
+            .body(serialize_hex(tx))
+            .send()
+            .await
+            .map_err(|e| WalletError::NetworkError(e.to_string()))?;
+
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|e| WalletError::NetworkError(e.to_string()))?;
+
+        if !status.is_success() {
+            return Err(WalletError::from_reject_reason(&reject_message(&body)));
+        }
+
+        Txid::from_str(body.trim())
+            .map_err(|e| WalletError::NetworkError(format!("Unexpected broadcast response: {}", e)))
+    }
+
+    /// Uses mempool.space's `/txs/test`, which proxies `testmempoolaccept`.
+    /// Plain Esplora servers (e.g. Blockstream's) do not offer it and answer
+    /// 404, reported as [`WalletError::Unsupported`]; point the API at a
+    /// mempool.space instance or a Core node to use `/wallet/test_accept`.
+    async fn test_mempool_accept(
+        &self,
+        txs: &[Transaction],
+    ) -> WalletResult<Vec<MempoolAcceptResult>> {
+        let raw_txs: Vec<String> = txs.iter().map(serialize_hex).collect();
+        let url = format!("{}/txs/test", self.base_url);
+        let response = self
+            .client
+            .post(&url)
+            .json(&raw_txs)
+            .send()
+            .await
+            .map_err(|e| WalletError::NetworkError(e.to_string()))?;
+
+        let status = response.status();
+        if status == StatusCode::NOT_FOUND {
+            return Err(WalletError::Unsupported(format!(
+                "{} has no /txs/test endpoint; mempool acceptance tests need \
+                 mempool.space or a Bitcoin Core backend",
+                self.base_url
+            )));
+        }
+        let body = response
+            .text()
+            .await
+            .map_err(|e| WalletError::NetworkError(e.to_string()))?;
+        if !status.is_success() {
+            return Err(WalletError::from_reject_reason(&reject_message(&body)));
+        }
+
+        let results: Vec<EsploraAcceptResult> =
+            serde_json::from_str(&body).map_err(|e| WalletError::NetworkError(e.to_string()))?;
+
+        Ok(results
+            .into_iter()
+            .map(|result| MempoolAcceptResult {
+                txid: result.txid,
+                allowed: result.allowed.unwrap_or(false),
+                reject_reason: result.reject_reason,
+                vsize: result.vsize,
+                fee: result
+                    .fees
+                    .and_then(|fees| Amount::from_btc(fees.base).ok()),
+            })
+            .collect())
+    }
+
+    async fn estimate_fee(&self, conf_target: u16) -> WalletResult<Option<FeeRate>> {
+        let estimates: HashMap<String, f64> =
+            self.get_json("/fee-estimates").await?.unwrap_or_default();
+
+        // Esplora only publishes a fixed set of targets: use the closest one
+        // that is at least as fast as requested.
+        let rate = estimates
+            .iter()
+            .filter_map(|(target, rate)| target.parse::<u16>().ok().map(|t| (t, *rate)))
+            .filter(|(target, _)| *target <= conf_target)
+            .max_by_key(|(target, _)| *target)
+            .map(|(_, rate)| rate);
+
+        Ok(rate.map(|sat_vb| FeeRate::from_sat_per_kwu((sat_vb * 250.0).ceil() as u64)))
+    }
+
+    async fn tip_height(&self) -> WalletResult<u32> {
+        let height = self
+            .get_text("/blocks/tip/height")
+            .await?
+            .ok_or_else(|| WalletError::NetworkError("Tip height not available".to_string()))?;
+
+        height
+            .trim()
+            .parse()
+            .map_err(|e| WalletError::NetworkError(format!("Invalid tip height: {}", e)))
+    }
+}
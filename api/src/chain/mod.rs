@@ -0,0 +1,158 @@
+// api/src/chain/mod.rs
+mod core_rpc;
+mod esplora;
+
+pub use core_rpc::{blocking_rpc, rpc_client, CoreRpcBackend};
+pub use esplora::EsploraBackend;
+
+use crate::error::{WalletError, WalletResult};
+use async_trait::async_trait;
+use bitcoin::{Address, Amount, FeeRate, OutPoint, Transaction, TxOut, Txid};
+use std::collections::{btree_map::Entry, BTreeMap};
+
+/// An unspent output paying to an address, as reported by a chain backend.
+#[derive(Debug, Clone)]
+pub struct Utxo {
+    pub outpoint: OutPoint,
+    pub value: Amount,
+    /// Height of the block that confirmed the output, `None` while in the mempool.
+    pub height: Option<u32>,
+}
+
+impl Utxo {
+    pub fn is_confirmed(&self) -> bool {
+        self.height.is_some()
+    }
+}
+
+/// A transaction that paid to or spent from an address, with its effect on
+/// that address.
+#[derive(Debug, Clone)]
+pub struct AddressTx {
+    pub txid: Txid,
+    /// Height of the confirming block, `None` while in the mempool.
+    pub height: Option<u32>,
+    /// Total fee of the transaction, if the backend reports it.
+    pub fee: Option<Amount>,
+    /// Sum of the outputs paying to the address.
+    pub received: Amount,
+    /// Sum of the address's outputs spent by the inputs.
+    pub sent: Amount,
+}
+
+/// Result of a mempool policy check for one transaction.
+#[derive(Debug, Clone)]
+pub struct MempoolAcceptResult {
+    pub txid: Txid,
+    pub allowed: bool,
+    pub reject_reason: Option<String>,
+    /// Only reported for allowed transactions.
+    pub vsize: Option<u64>,
+    /// Only reported for allowed transactions.
+    pub fee: Option<Amount>,
+}
+
+/// Outcome of one transaction in a package submission.
+#[derive(Debug, Clone)]
+pub struct PackageTxOutcome {
+    pub txid: Txid,
+    /// Reject reason, `None` if the transaction was accepted.
+    pub error: Option<String>,
+}
+
+/// Everything the API needs from the Bitcoin network.
+///
+/// Handlers and services only talk to the chain through this trait, so a
+/// deployment can switch between a Bitcoin Core node and an Esplora server
+/// without code changes.
+#[async_trait]
+pub trait ChainBackend: Send + Sync {
+    /// Short identifier used in logs.
+    fn name(&self) -> &'static str;
+
+    /// Lists the unspent outputs paying to `address`.
+    async fn get_utxos(&self, address: &Address) -> WalletResult<Vec<Utxo>>;
+
+    /// Unspent outputs of each of `addresses`, in the same order.
+    ///
+    /// Wallet scans go through this; backends that can look several
+    /// addresses up in one round trip override it, the default asks for
+    /// each in turn.
+    async fn get_utxos_batch(&self, addresses: &[Address]) -> WalletResult<Vec<Vec<Utxo>>> {
+        let mut utxos = Vec::with_capacity(addresses.len());
+        for address in addresses {
+            utxos.push(self.get_utxos(address).await?);
+        }
+        Ok(utxos)
+    }
+
+    /// Whether any transaction has ever paid to or spent from `address`.
+    ///
+    /// Backends without an address index report `false`; wallet scans then
+    /// treat an address as used only while it holds UTXOs.
+    async fn has_history(&self, _address: &Address) -> WalletResult<bool> {
+        Ok(false)
+    }
+
+    /// Every transaction touching `address`, newest first, following the
+    /// backend's pagination to the end.
+    async fn get_history(&self, _address: &Address) -> WalletResult<Vec<AddressTx>> {
+        Err(WalletError::NetworkError(format!(
+            "The {} backend does not index address history",
+            self.name()
+        )))
+    }
+
+    /// Fetches a transaction by id.
+    async fn get_transaction(&self, txid: &Txid) -> WalletResult<Transaction>;
+
+    /// Looks up an output, returning `None` if it does not exist or is already spent.
+    async fn get_tx_out(&self, outpoint: &OutPoint) -> WalletResult<Option<TxOut>>;
+
+    /// Submits a signed transaction to the network.
+    async fn broadcast(&self, tx: &Transaction) -> WalletResult<Txid>;
+
+    /// Submits an ordered package (parents first) in one call.
+    ///
+    /// Returns `None` when the backend has no package relay support, in which
+    /// case callers fall back to broadcasting the transactions one by one.
+    /// A package the node rejects as a whole is reported per transaction,
+    /// every one carrying the package's reject reason.
+    async fn submit_package(
+        &self,
+        _txs: &[Transaction],
+    ) -> WalletResult<Option<Vec<PackageTxOutcome>>> {
+        Ok(None)
+    }
+
+    /// Checks whether `txs` would be accepted to the mempool without
+    /// broadcasting them. Dependent transactions are evaluated as a package.
+    async fn test_mempool_accept(
+        &self,
+        txs: &[Transaction],
+    ) -> WalletResult<Vec<MempoolAcceptResult>>;
+
+    /// Estimates the fee rate needed to confirm within `conf_target` blocks,
+    /// `None` when the backend has no estimate yet.
+    async fn estimate_fee(&self, conf_target: u16) -> WalletResult<Option<FeeRate>>;
+
+    /// Height of the current chain tip.
+    async fn tip_height(&self) -> WalletResult<u32>;
+}
+
+/// Fetches the transactions spent by `tx`'s inputs, keyed by txid.
+pub async fn get_prev_txs(
+    chain: &dyn ChainBackend,
+    tx: &Transaction,
+) -> WalletResult<BTreeMap<Txid, Transaction>> {
+    let mut prev_txs = BTreeMap::new();
+
+    for input in &tx.input {
+        let txid = input.previous_output.txid;
+        if let Entry::Vacant(entry) = prev_txs.entry(txid) {
+            entry.insert(chain.get_transaction(&txid).await?);
+        }
+    }
+
+    Ok(prev_txs)
+}
@@ -0,0 +1,386 @@
+// api/src/config/mod.rs
+use crate::network::{default_esplora_url, network_name, parse_network};
+use crate::secret::Secret;
+use bitcoin::Network;
+use serde::{Serialize, Serializer};
+use std::{collections::BTreeMap, env, fmt, fs, net::SocketAddr};
+use thiserror::Error;
+
+/// Setting keys and the environment variables that override them.
+///
+/// Keys use the dotted form of the optional TOML file, e.g. `port` under a
+/// `[server]` table is `server.port`.
+const SETTINGS: &[(&str, &str)] = &[
+    ("server.host", "HOST"),
+    ("server.port", "PORT"),
+    ("network", "BITCOIN_NETWORK"),
+    ("chain.backend", "CHAIN_BACKEND"),
+    ("chain.esplora_url", "ESPLORA_URL"),
+    ("rpc.url", "BITCOIN_RPC_URL"),
+    ("rpc.host", "BITCOIN_RPC_HOST"),
+    ("rpc.port", "BITCOIN_RPC_PORT"),
+    ("rpc.user", "BITCOIN_RPC_USER"),
+    ("rpc.password", "BITCOIN_RPC_PASSWORD"),
+    ("fees.fast_target", "FEE_FAST_TARGET"),
+    ("fees.medium_target", "FEE_MEDIUM_TARGET"),
+    ("fees.slow_target", "FEE_SLOW_TARGET"),
+    ("fees.min_sat_vb", "FEE_MIN_SAT_VB"),
+    ("fees.max_sat_vb", "FEE_MAX_SAT_VB"),
+    ("fees.cache_secs", "FEE_CACHE_SECS"),
+    ("watch.gap_limit", "WATCH_GAP_LIMIT"),
+    ("send.min_change_sat", "SEND_MIN_CHANGE_SAT"),
+    ("send.spend_unconfirmed", "SEND_SPEND_UNCONFIRMED"),
+];
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Failed to read config file {0}: {1}")]
+    File(String, String),
+    #[error("Invalid value for {0}: {1}")]
+    Invalid(&'static str, String),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Config {
+    pub server: ServerConfig,
+    #[serde(serialize_with = "serialize_network")]
+    pub network: Network,
+    pub chain: ChainConfig,
+    pub rpc: RpcConfig,
+    pub fees: FeeConfig,
+    pub watch: WatchConfig,
+    pub send: SendConfig,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    Core,
+    Esplora,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChainConfig {
+    pub backend: BackendKind,
+    /// Resolved Esplora base URL, `None` when the network has no public default.
+    pub esplora_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FeeConfig {
+    /// Confirmation targets, in blocks, for the fast/medium/slow estimates.
+    pub fast_target: u16,
+    pub medium_target: u16,
+    pub slow_target: u16,
+    /// Estimates are clamped to this range, in sat/vB.
+    pub min_sat_vb: u64,
+    pub max_sat_vb: u64,
+    /// How long an estimate is reused before asking the backend again.
+    pub cache_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchConfig {
+    /// Consecutive unused addresses after which a descriptor scan stops.
+    pub gap_limit: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SendConfig {
+    /// Change below this many sats is left to the fee instead of creating an
+    /// output. Change below the dust limit is always dropped.
+    pub min_change_sat: u64,
+    /// Whether coin selection may spend outputs that are still in the mempool.
+    pub spend_unconfirmed: bool,
+}
+
+#[derive(Clone, Serialize)]
+pub struct RpcConfig {
+    /// Base URL of the node, without a `/wallet/<name>` suffix.
+    pub url: String,
+    pub user: String,
+    #[serde(serialize_with = "redact")]
+    pub password: Secret<String>,
+}
+
+impl fmt::Debug for RpcConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RpcConfig")
+            .field("url", &self.url)
+            .field("user", &self.user)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
+impl Config {
+    /// Loads the configuration from `CONFIG_FILE` (optional TOML) and the
+    /// environment, which takes precedence. Call after `load_env`.
+    pub fn load() -> Result<Self, ConfigError> {
+        let mut settings = BTreeMap::new();
+
+        if let Ok(path) = env::var("CONFIG_FILE") {
+            let contents = fs::read_to_string(&path)
+                .map_err(|e| ConfigError::File(path.clone(), e.to_string()))?;
+            let table: toml::Table = toml::from_str(&contents)
+                .map_err(|e| ConfigError::File(path.clone(), e.to_string()))?;
+            flatten_table("", &table, &mut settings);
+        }
+
+        for (key, var) in SETTINGS {
+            if let Ok(value) = env::var(var) {
+                settings.insert(key.to_string(), value);
+            }
+        }
+
+        Self::from_settings(&settings)
+    }
+
+    fn from_settings(settings: &BTreeMap<String, String>) -> Result<Self, ConfigError> {
+        let get = |key: &str| {
+            settings
+                .get(key)
+                .map(|v| v.trim())
+                .filter(|v| !v.is_empty())
+        };
+
+        let network_value = get("network").unwrap_or("testnet4");
+        let network = parse_network(network_value).ok_or_else(|| {
+            ConfigError::Invalid("network", format!("unknown network '{}'", network_value))
+        })?;
+
+        let server = ServerConfig {
+            host: get("server.host").unwrap_or("0.0.0.0").to_string(),
+            port: parse_port("server.port", get("server.port").unwrap_or("3333"))?,
+        };
+        server.socket_addr()?;
+
+        let backend = match get("chain.backend").unwrap_or("esplora") {
+            "core" => BackendKind::Core,
+            "esplora" => BackendKind::Esplora,
+            other => {
+                return Err(ConfigError::Invalid(
+                    "chain.backend",
+                    format!("expected 'core' or 'esplora', got '{}'", other),
+                ))
+            }
+        };
+        let esplora_url = match get("chain.esplora_url") {
+            Some(url) => Some(parse_http_url("chain.esplora_url", url)?),
+            None => default_esplora_url(network).map(str::to_string),
+        };
+        if backend == BackendKind::Esplora && esplora_url.is_none() {
+            return Err(ConfigError::Invalid(
+                "chain.esplora_url",
+                format!(
+                    "required for the esplora backend on {}",
+                    network_name(network)
+                ),
+            ));
+        }
+
+        let rpc_url = match get("rpc.url") {
+            Some(url) => parse_http_url("rpc.url", url)?,
+            None => {
+                let host = get("rpc.host").unwrap_or("localhost");
+                let port = match get("rpc.port") {
+                    Some(port) => parse_port("rpc.port", port)?,
+                    None => default_rpc_port(network),
+                };
+                format!("http://{}:{}", host, port)
+            }
+        };
+
+        let fees = FeeConfig {
+            fast_target: parse_number("fees.fast_target", get("fees.fast_target"), 2)?,
+            medium_target: parse_number("fees.medium_target", get("fees.medium_target"), 6)?,
+            slow_target: parse_number("fees.slow_target", get("fees.slow_target"), 144)?,
+            min_sat_vb: parse_number("fees.min_sat_vb", get("fees.min_sat_vb"), 1)?,
+            max_sat_vb: parse_number("fees.max_sat_vb", get("fees.max_sat_vb"), 1_000)?,
+            cache_secs: parse_number("fees.cache_secs", get("fees.cache_secs"), 60)?,
+        };
+        fees.validate()?;
+
+        let watch = WatchConfig {
+            gap_limit: parse_number("watch.gap_limit", get("watch.gap_limit"), 20)?,
+        };
+        if watch.gap_limit == 0 || watch.gap_limit > WatchConfig::MAX_GAP_LIMIT {
+            return Err(ConfigError::Invalid(
+                "watch.gap_limit",
+                format!("must be between 1 and {}", WatchConfig::MAX_GAP_LIMIT),
+            ));
+        }
+
+        let send = SendConfig {
+            min_change_sat: parse_number("send.min_change_sat", get("send.min_change_sat"), 1_000)?,
+            spend_unconfirmed: parse_bool(
+                "send.spend_unconfirmed",
+                get("send.spend_unconfirmed"),
+                false,
+            )?,
+        };
+
+        Ok(Self {
+            server,
+            network,
+            chain: ChainConfig {
+                backend,
+                esplora_url,
+            },
+            rpc: RpcConfig {
+                url: rpc_url,
+                user: get("rpc.user").unwrap_or("hello").to_string(),
+                password: Secret::new(get("rpc.password").unwrap_or("world").to_string()),
+            },
+            fees,
+            watch,
+            send,
+        })
+    }
+}
+
+impl ServerConfig {
+    pub fn socket_addr(&self) -> Result<SocketAddr, ConfigError> {
+        format!("{}:{}", self.host, self.port)
+            .parse()
+            .map_err(|e| ConfigError::Invalid("server.host", format!("{}", e)))
+    }
+}
+
+impl FeeConfig {
+    /// Bitcoin Core only estimates for targets between 1 and 1008 blocks.
+    pub const MAX_TARGET: u16 = 1008;
+
+    /// Far above any fee ever paid, and small enough that converting to
+    /// sat/kwu cannot overflow.
+    pub const MAX_SAT_VB: u64 = 1_000_000;
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        for (key, target) in [
+            ("fees.fast_target", self.fast_target),
+            ("fees.medium_target", self.medium_target),
+            ("fees.slow_target", self.slow_target),
+        ] {
+            if target == 0 || target > Self::MAX_TARGET {
+                return Err(ConfigError::Invalid(
+                    key,
+                    format!("must be between 1 and {} blocks", Self::MAX_TARGET),
+                ));
+            }
+        }
+        if !(self.fast_target <= self.medium_target && self.medium_target <= self.slow_target) {
+            return Err(ConfigError::Invalid(
+                "fees.medium_target",
+                "targets must be ordered fast <= medium <= slow".to_string(),
+            ));
+        }
+        if self.max_sat_vb > Self::MAX_SAT_VB {
+            return Err(ConfigError::Invalid(
+                "fees.max_sat_vb",
+                format!("must not be above {}", Self::MAX_SAT_VB),
+            ));
+        }
+        if self.min_sat_vb == 0 || self.min_sat_vb > self.max_sat_vb {
+            return Err(ConfigError::Invalid(
+                "fees.min_sat_vb",
+                "must be at least 1 and not above fees.max_sat_vb".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl WatchConfig {
+    /// Every scanned address costs a backend round trip; keep scans bounded.
+    pub const MAX_GAP_LIMIT: u32 = 1000;
+}
+
+/// Bitcoin Core's default RPC port for `network`.
+fn default_rpc_port(network: Network) -> u16 {
+    match network {
+        Network::Bitcoin => 8332,
+        Network::Testnet => 18332,
+        Network::Testnet4 => 48332,
+        Network::Signet => 38332,
+        Network::Regtest => 18443,
+    }
+}
+
+fn parse_port(key: &'static str, value: &str) -> Result<u16, ConfigError> {
+    value
+        .parse()
+        .map_err(|_| ConfigError::Invalid(key, format!("'{}' is not a port number", value)))
+}
+
+fn parse_number<T: std::str::FromStr>(
+    key: &'static str,
+    value: Option<&str>,
+    default: T,
+) -> Result<T, ConfigError> {
+    match value {
+        Some(value) => value
+            .parse()
+            .map_err(|_| ConfigError::Invalid(key, format!("'{}' is not a valid number", value))),
+        None => Ok(default),
+    }
+}
+
+fn parse_bool(key: &'static str, value: Option<&str>, default: bool) -> Result<bool, ConfigError> {
+    match value {
+        Some(value) => value
+            .parse()
+            .map_err(|_| ConfigError::Invalid(key, format!("'{}' is not true or false", value))),
+        None => Ok(default),
+    }
+}
+
+fn parse_http_url(key: &'static str, value: &str) -> Result<String, ConfigError> {
+    let url = reqwest::Url::parse(value).map_err(|e| ConfigError::Invalid(key, e.to_string()))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(ConfigError::Invalid(
+            key,
+            "expected an http(s) URL".to_string(),
+        ));
+    }
+    // URLs are shown by `GET /config`; credentials go in rpc.user and rpc.password.
+    if !url.username().is_empty() || url.password().is_some() {
+        return Err(ConfigError::Invalid(
+            key,
+            "must not contain credentials; set rpc.user and rpc.password instead".to_string(),
+        ));
+    }
+    Ok(value.trim_end_matches('/').to_string())
+}
+
+fn flatten_table(prefix: &str, table: &toml::Table, settings: &mut BTreeMap<String, String>) {
+    for (name, value) in table {
+        let key = if prefix.is_empty() {
+            name.clone()
+        } else {
+            format!("{}.{}", prefix, name)
+        };
+        match value {
+            toml::Value::Table(inner) => flatten_table(&key, inner, settings),
+            toml::Value::String(s) => {
+                settings.insert(key, s.clone());
+            }
+            other => {
+                settings.insert(key, other.to_string());
+            }
+        }
+    }
+}
+
+fn serialize_network<S: Serializer>(network: &Network, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(network_name(*network))
+}
+
+fn redact<S: Serializer>(_value: &Secret<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str("<redacted>")
+}
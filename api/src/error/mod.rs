@@ -21,24 +21,117 @@ pub enum WalletError {
     InvalidTransaction(String),
     #[error("Invalid key: {0}")]
     InvalidKey(String),
+    #[error("Invalid network: {0}")]
+    InvalidNetwork(String),
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("Wallet not found: {0}")]
+    WalletNotFound(String),
+    #[error("Inputs are worth less than outputs: {0}")]
+    InsufficientInputValue(String),
+    #[error("Fee too low: {0}")]
+    FeeTooLow(String),
+    #[error("Inputs missing or already spent: {0}")]
+    MissingInputs(String),
+    #[error("Transaction already known: {0}")]
+    AlreadyKnown(String),
+    #[error("Transaction rejected: {0}")]
+    TxRejected(String),
+    #[error("Insufficient funds: {0}")]
+    InsufficientFunds(String),
+    #[error("Unsupported by this backend: {0}")]
+    Unsupported(String),
 }
 
 pub type WalletResult<T> = Result<T, WalletError>;
 
+impl WalletError {
+    /// Maps a node's mempool reject reason (e.g. `bad-txns-in-belowout`) to a typed error.
+    pub fn from_reject_reason(reason: &str) -> Self {
+        let reason = reason.trim().to_string();
+        let matches = |needles: &[&str]| needles.iter().any(|needle| reason.contains(needle));
+
+        if matches(&["bad-txns-in-belowout"]) {
+            WalletError::InsufficientInputValue(reason)
+        } else if matches(&[
+            "min relay fee not met",
+            "mempool min fee not met",
+            "insufficient fee",
+            "min-fee-not-met",
+        ]) {
+            WalletError::FeeTooLow(reason)
+        } else if matches(&["missing-inputs", "missingorspent", "txn-mempool-conflict"]) {
+            WalletError::MissingInputs(reason)
+        } else if matches(&[
+            "txn-already-in-mempool",
+            "txn-already-known",
+            "already in block chain",
+        ]) {
+            WalletError::AlreadyKnown(reason)
+        } else {
+            WalletError::TxRejected(reason)
+        }
+    }
+
+    /// Stable identifier for mempool rejections and unfundable requests,
+    /// `None` for other errors.
+    pub fn code(&self) -> Option<&'static str> {
+        match self {
+            WalletError::InsufficientInputValue(_) => Some("insufficient_input_value"),
+            WalletError::FeeTooLow(_) => Some("fee_too_low"),
+            WalletError::MissingInputs(_) => Some("missing_inputs"),
+            WalletError::AlreadyKnown(_) => Some("already_known"),
+            WalletError::TxRejected(_) => Some("rejected"),
+            WalletError::InsufficientFunds(_) => Some("insufficient_funds"),
+            _ => None,
+        }
+    }
+
+    /// HTTP status the error is reported with.
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            WalletError::NetworkError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            WalletError::WalletNotFound(_) => StatusCode::NOT_FOUND,
+            WalletError::Unsupported(_) => StatusCode::NOT_IMPLEMENTED,
+            WalletError::MissingInputs(_) | WalletError::AlreadyKnown(_) => StatusCode::CONFLICT,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
 impl IntoResponse for WalletError {
     fn into_response(self) -> Response {
-        let (status, err_msg) = match self {
-            WalletError::BitcoinError(msg) => (StatusCode::BAD_REQUEST, msg),
-            WalletError::InvalidAddress(msg) => (StatusCode::BAD_REQUEST, msg),
-            WalletError::NetworkError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
-            WalletError::InvalidAmount(msg) => (StatusCode::BAD_REQUEST, msg),
-            WalletError::InvalidTransaction(msg) => (StatusCode::BAD_REQUEST, msg),
-            WalletError::InvalidKey(msg) => (StatusCode::BAD_REQUEST, msg),
+        let code = self.code();
+
+        let status = self.status_code();
+        let err_msg = match self {
+            WalletError::BitcoinError(msg)
+            | WalletError::InvalidAddress(msg)
+            | WalletError::NetworkError(msg)
+            | WalletError::InvalidAmount(msg)
+            | WalletError::InvalidTransaction(msg)
+            | WalletError::InvalidKey(msg)
+            | WalletError::InvalidNetwork(msg)
+            | WalletError::InvalidRequest(msg)
+            | WalletError::WalletNotFound(msg)
+            | WalletError::InsufficientInputValue(msg)
+            | WalletError::FeeTooLow(msg)
+            | WalletError::MissingInputs(msg)
+            | WalletError::AlreadyKnown(msg)
+            | WalletError::TxRejected(msg)
+            | WalletError::InsufficientFunds(msg)
+            | WalletError::Unsupported(msg) => msg,
         };
 
-        let body = Json(json!({
-            "error": err_msg
-        }));
+        let body = match code {
+            Some(code) => Json(json!({
+                "error": err_msg,
+                "code": code
+            })),
+            None => Json(json!({
+                "error": err_msg
+            })),
+        };
 
         (status, body).into_response()
     }
@@ -1,81 +1,124 @@
-use axum::{http::StatusCode, Json};
-use serde::{Deserialize, Serialize};
-use serde_json::json;
-use std::{env, process::Command};
-use tracing::{debug, error};
+// api/src/handlers/broadcast.rs
+use crate::{
+    error::{WalletError, WalletResult},
+    models::{
+        BroadcastPackageRequest, BroadcastTxRequest, BroadcastTxResponse, FinalizeRequest,
+        FinalizeResponse, TestAcceptRequest, TestAcceptResponse, TestAcceptResult,
+    },
+    services::{broadcast, psbt},
+    state::AppState,
+};
+use axum::{extract::State, response::IntoResponse, Json};
+use bitcoin::{consensus::deserialize, hashes::hex::FromHex, Transaction};
+use tracing::{debug, info};
 
-#[derive(Debug, Deserialize)]
-pub struct BroadcastRequest {
-    tx_hex: String,
+/// Decodes a raw transaction submitted by the client.
+fn decode_tx(tx_hex: &str) -> WalletResult<Transaction> {
+    let tx_bytes = Vec::<u8>::from_hex(tx_hex.trim())
+        .map_err(|e| WalletError::InvalidTransaction(format!("Invalid hex: {}", e)))?;
+    deserialize(&tx_bytes)
+        .map_err(|e| WalletError::InvalidTransaction(format!("Deserialization failed: {}", e)))
 }
 
-#[derive(Debug, Serialize)]
-pub struct BroadcastResponse {
-    txid: String,
-    command: String,
+fn decode_txs(tx_hexes: &[String]) -> WalletResult<Vec<Transaction>> {
+    tx_hexes.iter().map(|tx_hex| decode_tx(tx_hex)).collect()
 }
 
+/// Broadcasts a transaction to the Bitcoin network.
+/// Takes a raw transaction hex string that has been signed by the frontend.
 pub async fn broadcast_transaction(
-    Json(req): Json<BroadcastRequest>,
-) -> Result<Json<BroadcastResponse>, (StatusCode, Json<serde_json::Value>)> {
-    debug!("Broadcasting transaction with hex: {}", req.tx_hex);
-
-    let rpc_host = env::var("BITCOIN_RPC_HOST").unwrap_or_else(|_| "localhost".to_string());
-    let rpc_port = env::var("BITCOIN_RPC_PORT").unwrap_or_else(|_| "48332".to_string());
-    let rpc_user = env::var("BITCOIN_RPC_USER").unwrap_or_else(|_| "hello".to_string());
-    let rpc_password = env::var("BITCOIN_RPC_PASSWORD").unwrap_or_else(|_| "world".to_string());
-
-    let args = vec![
-        &format!("-rpcconnect={}", rpc_host),
-        &format!("-rpcport={}", rpc_port),
-        &format!("-rpcuser={}", rpc_user),
-        &format!("-rpcpassword={}", rpc_password),
-        "sendrawtransaction",
-        &req.tx_hex,
-    ];
-    debug!("Executing bitcoin-cli with args: {:?}", args);
+    State(state): State<AppState>,
+    Json(req): Json<BroadcastTxRequest>,
+) -> impl IntoResponse {
+    let tx = match decode_tx(&req.tx_hex) {
+        Ok(tx) => tx,
+        Err(e) => return e.into_response(),
+    };
+    debug!(
+        "Broadcasting {} via {}",
+        tx.compute_txid(),
+        state.chain.name()
+    );
 
-    let cmd_out = Command::new("bitcoin-cli")
-        .args(&args)
-        .output()
-        .map_err(|e| {
-            error!("Failed to execute bitcoin-cli: {}", e);
-            (
-                StatusCode::BAD_REQUEST,
-                Json(json!({
-                    "status": "error",
-                    "message": format!("Failed to broadcast transaction: {}", e)
-                })),
-            )
-        })?;
+    match state.chain.broadcast(&tx).await {
+        Ok(txid) => {
+            info!("Broadcast transaction {}", txid);
+            Json(BroadcastTxResponse {
+                txid: txid.to_string(),
+            })
+            .into_response()
+        }
+        Err(e) => e.into_response(),
+    }
+}
 
-    let stdout = String::from_utf8(cmd_out.stdout).unwrap_or_else(|_| "Invalid UTF-8".to_string());
-    let stderr = String::from_utf8_lossy(&cmd_out.stderr);
+/// Broadcasts an ordered package such as the `commit_tx`/`spell_tx` pair
+/// returned by `prove_spell`, reporting the outcome of each transaction.
+pub async fn broadcast_package(
+    State(state): State<AppState>,
+    Json(req): Json<BroadcastPackageRequest>,
+) -> impl IntoResponse {
+    let txs = match decode_txs(&req.txs) {
+        Ok(txs) => txs,
+        Err(e) => return e.into_response(),
+    };
 
-    debug!("bitcoin-cli stdout: {}", stdout);
-    debug!("bitcoin-cli stderr: {}", stderr);
+    match broadcast::broadcast_package(state.chain.as_ref(), &txs).await {
+        Ok(result) => Json(result).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
 
-    if !cmd_out.status.success() {
-        error!(
-            "bitcoin-cli failed. Status: {}, stdout: {}, stderr: {}",
-            cmd_out.status, stdout, stderr
-        );
-        return Err((
-            StatusCode::BAD_REQUEST,
-            Json(json!({
-                "status": "error",
-                "message": format!("Failed to broadcast transaction. stdout: {}, stderr: {}", stdout, stderr)
-            })),
-        ));
+/// Combines signed PSBTs and returns the final transactions, ready for
+/// `/wallet/broadcast` or `/wallet/broadcast_package`.
+pub async fn finalize_psbts(Json(req): Json<FinalizeRequest>) -> impl IntoResponse {
+    match psbt::finalize(&req.psbts) {
+        Ok(transactions) => Json(FinalizeResponse { transactions }).into_response(),
+        Err(e) => e.into_response(),
     }
+}
 
-    let txid = stdout.trim().to_string();
+/// Runs `testmempoolaccept` on one or more raw transactions without
+/// broadcasting them, so a spell pair can be checked before it is signed and sent.
+/// Esplora backends other than mempool.space answer 501.
+pub async fn test_accept(
+    State(state): State<AppState>,
+    Json(req): Json<TestAcceptRequest>,
+) -> impl IntoResponse {
+    let txs = match decode_txs(&req.txs) {
+        Ok(txs) if !txs.is_empty() => txs,
+        Ok(_) => {
+            return WalletError::InvalidTransaction("No transactions provided".to_string())
+                .into_response()
+        }
+        Err(e) => return e.into_response(),
+    };
 
-    let command = format!(
-        "bitcoin-cli -rpcconnect={} -rpcport={} -rpcuser={} -rpcpassword={} sendrawtransaction {}",
-        rpc_host, rpc_port, rpc_user, rpc_password, req.tx_hex
-    );
-    debug!("Full command: {}", command);
+    match state.chain.test_mempool_accept(&txs).await {
+        Ok(results) => {
+            let results: Vec<TestAcceptResult> = results
+                .into_iter()
+                .map(|result| TestAcceptResult {
+                    txid: result.txid.to_string(),
+                    allowed: result.allowed,
+                    reject_code: result.reject_reason.as_deref().map(|reason| {
+                        WalletError::from_reject_reason(reason)
+                            .code()
+                            .unwrap_or("rejected")
+                            .to_string()
+                    }),
+                    reject_reason: result.reject_reason,
+                    vsize: result.vsize,
+                    fees: result.fee,
+                })
+                .collect();
 
-    Ok(Json(BroadcastResponse { txid, command }))
+            Json(TestAcceptResponse {
+                allowed: results.iter().all(|r| r.allowed),
+                results,
+            })
+            .into_response()
+        }
+        Err(e) => e.into_response(),
+    }
 }
@@ -1,18 +1,151 @@
 // api/src/handlers/external.rs
-use crate::{models::BroadcastTxRequest, services::ExternalWalletService};
-use axum::{extract::Path, response::IntoResponse, Json};
+use crate::{
+    models::{AddressesQuery, UnitQuery, WatchWalletRequest},
+    state::AppState,
+};
+use axum::{
+    extract::{Path, Query, State},
+    response::IntoResponse,
+    Json,
+};
 
-pub async fn get_balance(Path(address): Path<String>) -> impl IntoResponse {
-    let service = ExternalWalletService::new();
-    match service.get_balance(&address).await {
+/// Addresses listed per chain when the query does not set `count`.
+const DEFAULT_ADDRESS_COUNT: u32 = 20;
+
+pub async fn get_balance(
+    State(state): State<AppState>,
+    Path(address): Path<String>,
+    Query(query): Query<UnitQuery>,
+) -> impl IntoResponse {
+    let unit = match query.denomination() {
+        Ok(unit) => unit,
+        Err(e) => return e.into_response(),
+    };
+
+    match state.external.get_balance(&address, unit).await {
+        Ok(result) => Json(result).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+pub async fn get_address_history(
+    State(state): State<AppState>,
+    Path(address): Path<String>,
+) -> impl IntoResponse {
+    match state.external.get_history(&address).await {
+        Ok(result) => Json(result).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+pub async fn get_address_utxos(
+    State(state): State<AppState>,
+    Path(address): Path<String>,
+) -> impl IntoResponse {
+    match state.external.get_address_utxos(&address).await {
+        Ok(result) => Json(result).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+pub async fn watch_wallet(
+    State(state): State<AppState>,
+    Json(payload): Json<WatchWalletRequest>,
+) -> impl IntoResponse {
+    match state
+        .local
+        .watch_wallet(&payload, state.config.watch.gap_limit)
+        .await
+    {
+        Ok(result) => Json(result).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+pub async fn get_wallet_balance(
+    State(state): State<AppState>,
+    Path(wallet_id): Path<String>,
+    Query(query): Query<UnitQuery>,
+) -> impl IntoResponse {
+    let unit = match query.denomination() {
+        Ok(unit) => unit,
+        Err(e) => return e.into_response(),
+    };
+    let chains = match state.wallet_chains(&wallet_id).await {
+        Ok(chains) => chains,
+        Err(e) => return e.into_response(),
+    };
+
+    match state
+        .external
+        .get_wallet_balance(&wallet_id, &chains, unit)
+        .await
+    {
+        Ok(result) => Json(result).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+pub async fn get_wallet_utxos(
+    State(state): State<AppState>,
+    Path(wallet_id): Path<String>,
+) -> impl IntoResponse {
+    let chains = match state.wallet_chains(&wallet_id).await {
+        Ok(chains) => chains,
+        Err(e) => return e.into_response(),
+    };
+
+    match state
+        .external
+        .get_wallet_utxos(&wallet_id, &chains, false)
+        .await
+    {
         Ok(result) => Json(result).into_response(),
         Err(e) => e.into_response(),
     }
 }
 
-pub async fn broadcast_transaction(Json(payload): Json<BroadcastTxRequest>) -> impl IntoResponse {
-    let service = ExternalWalletService::new();
-    match service.broadcast_transaction(&payload).await {
+pub async fn get_wallet_charms(
+    State(state): State<AppState>,
+    Path(wallet_id): Path<String>,
+) -> impl IntoResponse {
+    let chains = match state.wallet_chains(&wallet_id).await {
+        Ok(chains) => chains,
+        Err(e) => return e.into_response(),
+    };
+
+    match state
+        .external
+        .get_wallet_utxos(&wallet_id, &chains, true)
+        .await
+    {
+        Ok(result) => Json(result).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Addresses of a watch-only or node wallet, derived from its descriptors.
+pub async fn get_wallet_addresses(
+    State(state): State<AppState>,
+    Path(wallet_id): Path<String>,
+    Query(query): Query<AddressesQuery>,
+) -> impl IntoResponse {
+    let chains = match state.wallet_chains(&wallet_id).await {
+        Ok(chains) => chains,
+        Err(e) => return e.into_response(),
+    };
+
+    match state
+        .external
+        .derive_addresses(
+            &wallet_id,
+            &chains,
+            query.kind,
+            query.from,
+            query.count.unwrap_or(DEFAULT_ADDRESS_COUNT),
+        )
+        .await
+    {
         Ok(result) => Json(result).into_response(),
         Err(e) => e.into_response(),
     }
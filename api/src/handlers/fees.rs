@@ -0,0 +1,11 @@
+// api/src/handlers/fees.rs
+use crate::state::AppState;
+use axum::{extract::State, response::IntoResponse, Json};
+
+/// Fast, medium and slow fee rates in sat/vB.
+pub async fn get_fees(State(state): State<AppState>) -> impl IntoResponse {
+    match state.fees.estimates().await {
+        Ok(result) => Json(result).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
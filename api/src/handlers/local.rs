@@ -1,20 +1,55 @@
 // api/src/handlers/local.rs
-use axum::{Json, response::IntoResponse};
 use crate::{
-    models::CreateWalletRequest,
-    services::LocalWalletService,
+    models::{CreateWalletRequest, ImportWalletRequest, NewAddressQuery},
+    state::AppState,
+};
+use axum::{
+    extract::{Path, Query, State},
+    response::IntoResponse,
+    Json,
 };
 
 pub async fn create_wallet(
+    State(state): State<AppState>,
     Json(payload): Json<CreateWalletRequest>,
 ) -> impl IntoResponse {
-    match LocalWalletService::new() {
-        Ok(service) => {
-            match service.create_wallet(&payload.password).await {
-                Ok(result) => Json(result).into_response(),
-                Err(e) => e.into_response(),
-            }
-        },
+    match state.local.create_wallet(&payload).await {
+        Ok(result) => Json(result).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+pub async fn new_address(
+    State(state): State<AppState>,
+    Path(wallet_id): Path<String>,
+    Query(query): Query<NewAddressQuery>,
+) -> impl IntoResponse {
+    match state
+        .local
+        .new_address(&wallet_id, query.address_type)
+        .await
+    {
+        Ok(result) => Json(result).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+pub async fn import_wallet(
+    State(state): State<AppState>,
+    Json(payload): Json<ImportWalletRequest>,
+) -> impl IntoResponse {
+    match state.local.import_wallet(&payload).await {
+        Ok(result) => Json(result).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+pub async fn rescan_status(
+    State(state): State<AppState>,
+    Path(wallet_id): Path<String>,
+) -> impl IntoResponse {
+    match state.local.rescan_status(&wallet_id).await {
+        Ok(result) => Json(result).into_response(),
         Err(e) => e.into_response(),
     }
-}
\ No newline at end of file
+}
@@ -1,6 +1,9 @@
 // api/src/handlers/mod.rs
+mod broadcast;
 mod external;
+mod fees;
 mod local;
+mod send;
 mod transfer_charms;
 
 mod health {
@@ -11,7 +14,25 @@ mod health {
     }
 }
 
-pub use external::{broadcast_transaction, get_balance};
+mod debug {
+    use crate::config::Config;
+    use axum::{extract::State, Json};
+    use std::sync::Arc;
+
+    /// Returns the active configuration with secrets redacted.
+    pub async fn get_config(State(config): State<Arc<Config>>) -> Json<Config> {
+        Json(config.as_ref().clone())
+    }
+}
+
+pub use broadcast::{broadcast_package, broadcast_transaction, finalize_psbts, test_accept};
+pub use debug::get_config;
+pub use external::{
+    get_address_history, get_address_utxos, get_balance, get_wallet_addresses, get_wallet_balance,
+    get_wallet_charms, get_wallet_utxos, watch_wallet,
+};
+pub use fees::get_fees;
 pub use health::health_check;
-pub use local::create_wallet;
-pub use transfer_charms::transfer_charms;
+pub use local::{create_wallet, import_wallet, new_address, rescan_status};
+pub use send::build_transaction;
+pub use transfer_charms::prove_spell;
@@ -0,0 +1,22 @@
+// api/src/handlers/send.rs
+use crate::{models::TransactionRequest, state::AppState};
+use axum::{extract::State, response::IntoResponse, Json};
+
+/// Builds an unsigned PSBT paying `to_address` from a wallet or an address.
+pub async fn build_transaction(
+    State(state): State<AppState>,
+    Json(payload): Json<TransactionRequest>,
+) -> impl IntoResponse {
+    let chains = match &payload.wallet_id {
+        Some(wallet_id) => match state.wallet_chains(wallet_id).await {
+            Ok(chains) => chains,
+            Err(e) => return e.into_response(),
+        },
+        None => Vec::new(),
+    };
+
+    match state.send.build_psbt(&payload, &chains).await {
+        Ok(result) => Json(result).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
@@ -1,22 +1,20 @@
+use crate::chain::get_prev_txs;
+use crate::error::WalletError;
 use crate::models::TransferCharmsRequest;
-use axum::{http::StatusCode, Json};
-use bitcoin::{
-    consensus::encode,
-    secp256k1::{Keypair, Secp256k1},
-    Amount, FeeRate, XOnlyPublicKey,
-};
-use charms::{
-    script, spell::prove_spell_tx, spell::NormalizedSpell, spell::Spell, tx, wallet::get_prev_txs,
-};
-use rand::thread_rng;
+use crate::network::parse_address;
+use crate::state::AppState;
+use axum::{extract::State, http::StatusCode, Json};
+use bitcoin::{consensus::encode, AddressType, Amount, Transaction, Txid};
+use charms::{spell::prove_spell_tx, spell::Spell, tx};
 use serde_json::json;
-use std::path::PathBuf;
-use std::str::FromStr;
+use std::{collections::BTreeMap, path::PathBuf};
 use tracing::{debug, error, info};
 
-use crate::services::local::{get_change_address, get_funding_utxo_value, parse_outpoint};
+use crate::services::local::{get_funding_utxo, parse_outpoint};
+use crate::services::{commit, psbt};
 
 pub async fn prove_spell(
+    State(state): State<AppState>,
     Json(req): Json<TransferCharmsRequest>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
     info!("=== Starting prove_spell handler ===");
@@ -73,20 +71,23 @@ pub async fn prove_spell(
     let app_bins: Vec<PathBuf> = vec![];
 
     // 3 Get the previous transactions
-    let prev_txs = txs_by_txid(wallet::get_prev_txs(&tx)?)?;
-
-    // RJJ we need to call the prove_spell_tx function here that will return the 2 txs to sign
-
-    /*
-    spell: Spell,
-    tx: bitcoin::Transaction,
-    app_bins: Vec<PathBuf>,
-    prev_txs: BTreeMap<Txid, bitcoin::Transaction>,
-    funding_utxo: OutPoint,
-    funding_utxo_value: u64,
-    change_address: String,
-    fee_rate: f64,
-    */
+    debug!("Getting previous transactions");
+    let prev_txs_map = match get_prev_txs(state.chain.as_ref(), &tx).await {
+        Ok(map) => {
+            debug!("Previous transactions processed successfully");
+            map
+        }
+        Err(e) => {
+            error!("Failed to get previous transactions: {}", e);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({
+                    "status": "error",
+                    "message": format!("Failed to get previous transactions: {}", e)
+                })),
+            ));
+        }
+    };
 
     // Get funding utxo and value
     debug!("Getting funding UTXO from: {}", req.funding_utxo_id);
@@ -104,11 +105,10 @@ pub async fn prove_spell(
         }
     };
 
-    let funding_utxo_value = match get_funding_utxo_value(funding_utxo) {
-        Ok(value) => {
-            let amount = Amount::from_sat(value);
-            debug!("Funding UTXO value: {} sats", value);
-            amount
+    let funding_output = match get_funding_utxo(state.chain.as_ref(), funding_utxo).await {
+        Ok(output) => {
+            debug!("Funding UTXO value: {} sats", output.value.to_sat());
+            output
         }
         Err(e) => {
             error!("Failed to get funding UTXO value: {}", e);
@@ -122,128 +122,226 @@ pub async fn prove_spell(
         }
     };
 
-    // Get change address
-    debug!("Getting change address");
-    let change_address = match get_change_address() {
-        Ok(addr) => addr,
+    // Change goes back to the user: an address they gave, or the next unused
+    // one from their wallet. There is no fallback to the node's own wallet.
+    debug!("Resolving change address");
+    let change_address = match (&req.change_address, &req.wallet_id) {
+        (Some(address), _) => parse_address(address, state.config.network),
+        (None, Some(wallet_id)) => match state.wallet_chains(wallet_id).await {
+            Ok(chains) => state.local.next_change_address(wallet_id, &chains).await,
+            Err(e) => Err(e),
+        },
+        (None, None) => Err(WalletError::InvalidAddress(
+            "change_address or wallet_id is required".to_string(),
+        )),
+    };
+    let change_address = match change_address {
+        Ok(address) => address,
         Err(e) => {
-            error!("Failed to get change address: {}", e);
+            error!("No usable change address: {}", e);
             return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
+                e.status_code(),
                 Json(json!({
                     "status": "error",
-                    "message": "Failed to generate change address"
+                    "message": e.to_string()
                 })),
             ));
         }
     };
 
-    let change_script_pubkey = match bitcoin::Address::from_str(&change_address) {
-        Ok(addr) => {
-            debug!("Change address: {}", change_address);
-            addr.assume_checked().script_pubkey()
-        }
-        Err(e) => {
-            error!("Invalid change address format: {}", e);
+    if !matches!(
+        change_address.address_type(),
+        Some(AddressType::P2wpkh) | Some(AddressType::P2tr)
+    ) {
+        error!("Unsupported change address type: {}", change_address);
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "status": "error",
+                "message": "change_address must be a P2WPKH or P2TR address"
+            })),
+        ));
+    }
+    debug!("Change address: {}", change_address);
+    let change_script_pubkey = change_address.script_pubkey();
+
+    // Fee rate chosen by the caller, or estimated for their confirmation target
+    let fee_rate = match state
+        .fees
+        .requested_fee_rate(req.fee_rate_sat_vb, req.conf_target)
+        .await
+    {
+        Ok(rate) => rate,
+        Err(WalletError::InvalidAmount(msg)) => {
+            error!("Invalid fee choice: {}", msg);
             return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::BAD_REQUEST,
                 Json(json!({
                     "status": "error",
-                    "message": "Invalid change address format"
+                    "message": msg
                 })),
             ));
         }
-    };
-
-    // Get previous transactions
-    debug!("Getting previous transactions");
-    let prev_txs = match get_prev_txs(&tx) {
-        Ok(txs) => txs,
         Err(e) => {
-            error!("Failed to get previous transactions: {}", e);
+            error!("Failed to estimate fee rate: {}", e);
             return Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(json!({
                     "status": "error",
-                    "message": format!("Failed to get previous transactions: {}", e)
+                    "message": "Failed to estimate fee rate"
                 })),
             ));
         }
     };
+    debug!("Using fee rate: {} sat/kwu", fee_rate.to_sat_per_kwu());
 
-    let prev_txs_map = match tx::txs_by_txid(prev_txs) {
-        Ok(map) => {
-            debug!("Previous transactions processed successfully");
-            map
+    // Prove the spell. The prover returns the proof inside a commit/spell
+    // pair of its own, signed with a key we never see; only the proven spell
+    // data is kept and committed again below under our own one-off key.
+    debug!("Proving spell");
+    let fee_rate_sat_vb = fee_rate.to_sat_per_kwu() as f64 / 250.0;
+    let proving = {
+        let tx = tx.clone();
+        let prev_txs = prev_txs_map.clone();
+        let change_address = change_address.to_string();
+        let funding_utxo_value = funding_output.value.to_sat();
+        tokio::task::spawn_blocking(move || {
+            prove_spell_tx(
+                spell,
+                tx,
+                app_bins,
+                prev_txs,
+                funding_utxo,
+                funding_utxo_value,
+                change_address,
+                fee_rate_sat_vb,
+            )
+        })
+        .await
+    };
+    let [proven_commit_tx, proven_spell_tx] = match proving {
+        Ok(Ok(txs)) => txs,
+        Ok(Err(e)) => {
+            error!("Failed to prove spell: {}", e);
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "status": "error",
+                    "message": format!("Failed to prove spell: {}", e)
+                })),
+            ));
         }
         Err(e) => {
-            error!("Failed to process previous transactions: {}", e);
+            error!("Prover task failed: {}", e);
             return Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(json!({
                     "status": "error",
-                    "message": format!("Failed to process previous transactions: {}", e)
+                    "message": "Failed to prove spell"
                 })),
             ));
         }
     };
-
-    // Parse fee rate (using minimum fee rate)
-    let fee_rate = FeeRate::from_sat_per_kwu(500);
-    debug!("Using fee rate: {} sat/kwu", 500);
-
-    // Get spell data
-    debug!("Normalizing spell and preparing spell data");
-    let (norm_spell, _) = spell.normalized().map_err(|e| {
-        error!("Failed to normalize spell: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({
-                "status": "error",
-                "message": format!("Failed to normalize spell: {}", e)
-            })),
-        )
-    })?;
-
-    let spell_data = charms_data::util::write::<(&NormalizedSpell, &[u8])>(&(&norm_spell, &[]))
-        .map_err(|e| {
-            error!("Failed to serialize spell data: {}", e);
-            (
+    let spell_data = match commit::spell_data(&proven_commit_tx, &proven_spell_tx) {
+        Ok(spell_data) => spell_data,
+        Err(e) => {
+            error!("Unexpected prover output: {}", e);
+            return Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(json!({
                     "status": "error",
-                    "message": format!("Failed to prepare spell data: {}", e)
+                    "message": format!("Unexpected prover output: {}", e)
                 })),
-            )
-        })?;
-    debug!("Spell data prepared successfully");
+            ));
+        }
+    };
+    debug!("Spell proved, {} bytes of spell data", spell_data.len());
 
-    // Create both transactions using add_spell
+    // Create both transactions; the spell tx's commit input is signed here
+    // with a one-off key that is wiped before this returns. Charms must then
+    // read the same spell from our spell tx as from the prover's.
     debug!("Creating commit and spell transactions");
-    let secp256k1 = Secp256k1::new();
-    let keypair = Keypair::new(&secp256k1, &mut thread_rng());
-    let (public_key, _) = XOnlyPublicKey::from_keypair(&keypair);
-
-    // Create the script and control block
-    let script = script::data_script(public_key, &spell_data);
-    let control_block = script::control_block(public_key, script.clone());
-
-    let [commit_tx, spell_tx] = tx::add_spell(
+    let spell_txs = match commit::build(
         tx,
         &spell_data,
         funding_utxo,
-        funding_utxo_value,
+        &funding_output,
         change_script_pubkey,
         fee_rate,
         &prev_txs_map,
-    );
+    ) {
+        Ok(spell_txs) => spell_txs,
+        Err(e) => {
+            error!("Failed to create transactions: {}", e);
+            return Err((
+                e.status_code(),
+                Json(json!({
+                    "status": "error",
+                    "message": format!("Failed to create transactions: {}", e)
+                })),
+            ));
+        }
+    };
+    if let Err(e) = commit::verify_spell(&proven_spell_tx, &spell_txs.spell_tx) {
+        error!("Rebuilt spell tx does not match the prover's: {}", e);
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({
+                "status": "error",
+                "message": format!("Unexpected prover output: {}", e)
+            })),
+        ));
+    }
+    let user_inputs = spell_txs.user_inputs();
+    let commit::SpellTxs {
+        commit_tx,
+        spell_tx,
+        ..
+    } = spell_txs;
     debug!("Transactions created successfully");
 
+    // The commit tx spends the funding UTXO; the spell tx spends the spell's
+    // inputs plus the commit output.
+    let commit_fee = funding_output.value.checked_sub(output_value(&commit_tx));
+    let mut spell_prevouts = prev_txs_map.clone();
+    spell_prevouts.insert(commit_tx.compute_txid(), commit_tx.clone());
+    let spell_fee = input_value(&spell_tx, &spell_prevouts)
+        .and_then(|inputs| inputs.checked_sub(output_value(&spell_tx)));
+
+    // Wrap both transactions for signing; the spell PSBT carries the commit
+    // output's tapscript leaf, control block and internal key, and the
+    // already signed commit input's final witness.
+    let psbts = psbt::commit_psbt(&commit_tx, funding_output).and_then(|commit_psbt| {
+        psbt::spell_psbt(&spell_tx, &commit_tx, &prev_txs_map)
+            .map(|spell_psbt| (commit_psbt, spell_psbt))
+    });
+    let (commit_psbt, spell_psbt) = match psbts {
+        Ok(psbts) => psbts,
+        Err(e) => {
+            error!("Failed to build PSBTs: {}", e);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({
+                    "status": "error",
+                    "message": format!("Failed to build PSBTs: {}", e)
+                })),
+            ));
+        }
+    };
+    let taproot_data = spell_psbt
+        .inputs
+        .iter()
+        .find_map(|input| input.tap_scripts.iter().next())
+        .map(|(control_block, (script, _))| {
+            json!({
+                "script": script.to_hex_string(),
+                "control_block": hex::encode(control_block.serialize())
+            })
+        });
+
     // Serialize transactions and additional data
     let commit_tx_hex = encode::serialize_hex(&commit_tx);
     let spell_tx_hex = encode::serialize_hex(&spell_tx);
-    let script_hex = script.to_string();
-    let control_block_hex = hex::encode(control_block.serialize());
 
     Ok(Json(json!({
         "status": "success",
@@ -251,10 +349,45 @@ pub async fn prove_spell(
         "transactions": {
             "commit_tx": commit_tx_hex,
             "spell_tx": spell_tx_hex,
-            "taproot_data": {
-                "script": script_hex,
-                "control_block": control_block_hex
+            "taproot_data": taproot_data
+        },
+        "psbts": {
+            "commit_tx": commit_psbt.to_string(),
+            "spell_tx": spell_psbt.to_string()
+        },
+        "inputs_to_sign": {
+            "commit_tx": [0],
+            "spell_tx": user_inputs
+        },
+        "fees": {
+            "fee_rate_sat_vb": fee_rate.to_sat_per_vb_ceil(),
+            "commit_tx": {
+                "fee": commit_fee.map(Amount::to_sat),
+                "vsize": commit_tx.vsize()
+            },
+            "spell_tx": {
+                "fee": spell_fee.map(Amount::to_sat),
+                "vsize": spell_tx.vsize()
             }
         }
     })))
 }
+
+fn output_value(tx: &Transaction) -> Amount {
+    tx.output.iter().map(|out| out.value).sum()
+}
+
+/// Total value spent by `tx`, or `None` if a previous output is unknown.
+fn input_value(tx: &Transaction, prev_txs: &BTreeMap<Txid, Transaction>) -> Option<Amount> {
+    tx.input
+        .iter()
+        .map(|input| {
+            let prevout = input.previous_output;
+            prev_txs
+                .get(&prevout.txid)?
+                .output
+                .get(prevout.vout as usize)
+                .map(|out| out.value)
+        })
+        .sum()
+}
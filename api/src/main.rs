@@ -1,16 +1,25 @@
 // api/src/main.rs
+mod chain;
+mod config;
 mod error;
 mod handlers;
 mod models;
+mod network;
+mod secret;
 mod services;
+mod state;
 
 use axum::{
+    extract::Request,
+    middleware,
     routing::{get, post},
-    Router,
+    Router, ServiceExt,
 };
-use dotenv::dotenv;
+use config::Config;
 use http::{header, Method};
-use std::{env, net::SocketAddr, str::FromStr, time::Duration};
+use state::AppState;
+use std::{env, process, time::Duration};
+use tower::Layer;
 use tower_http::cors::{Any, CorsLayer};
 
 fn load_env() {
@@ -30,6 +39,23 @@ async fn main() {
     load_env();
     tracing_subscriber::fmt::init();
 
+    let config = Config::load().unwrap_or_else(|e| {
+        tracing::error!("Invalid configuration: {}", e);
+        process::exit(1);
+    });
+    let network = config.network;
+    let addr = config.server.socket_addr().expect("Invalid socket address");
+    tracing::info!(
+        "Serving {} through the {:?} chain backend",
+        network::network_name(network),
+        config.chain.backend
+    );
+
+    let state = AppState::new(config).unwrap_or_else(|e| {
+        tracing::error!("Failed to initialise application state: {}", e);
+        process::exit(1);
+    });
+
     tracing::info!("Configuring CORS with permissive settings");
     let cors = CorsLayer::new()
         .allow_origin(Any)
@@ -51,9 +77,43 @@ async fn main() {
     tracing::info!("Setting up routes with CORS logging");
     let app = Router::new()
         .route("/health", get(handlers::health_check))
+        .route("/config", get(handlers::get_config))
         .route("/wallet/create", post(handlers::create_wallet))
+        .route("/wallet/import", post(handlers::import_wallet))
+        .route("/wallet/{wallet_id}/address", get(handlers::new_address))
+        .route("/wallet/{wallet_id}/rescan", get(handlers::rescan_status))
+        .route(
+            "/wallet/{wallet_id}/addresses",
+            get(handlers::get_wallet_addresses),
+        )
         .route("/wallet/balance/{address}", get(handlers::get_balance))
+        .route("/wallet/utxos/{address}", get(handlers::get_address_utxos))
+        .route(
+            "/wallet/history/{address}",
+            get(handlers::get_address_history),
+        )
+        .route("/wallet/fees", get(handlers::get_fees))
+        .route("/wallet/watch", post(handlers::watch_wallet))
+        .route(
+            "/wallet/watch/{wallet_id}/balance",
+            get(handlers::get_wallet_balance),
+        )
+        .route(
+            "/wallet/watch/{wallet_id}/utxos",
+            get(handlers::get_wallet_utxos),
+        )
+        .route(
+            "/wallet/watch/{wallet_id}/charms",
+            get(handlers::get_wallet_charms),
+        )
         .route("/wallet/broadcast", post(handlers::broadcast_transaction))
+        .route(
+            "/wallet/broadcast_package",
+            post(handlers::broadcast_package),
+        )
+        .route("/wallet/test_accept", post(handlers::test_accept))
+        .route("/wallet/build_psbt", post(handlers::build_transaction))
+        .route("/wallet/finalize", post(handlers::finalize_psbts))
         .route(
             "/wallet/prove_spell",
             get(|| async {
@@ -66,20 +126,23 @@ async fn main() {
                 "OK"
             }),
         )
-        .layer(cors);
+        .with_state(state);
 
     tracing::info!("Added all routes with OPTIONS handlers");
 
     tracing::info!("CORS and routes configured successfully");
 
-    let host = env::var("HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
-    let port = env::var("PORT").unwrap_or("3333".to_string());
-    let port: u16 = port.parse().expect("PORT must be a number");
+    // Runs before routing so that `/{network}/wallet/...` can be stripped to `/wallet/...`.
+    // CORS wraps it, so its network errors reach the browser like any other.
+    let app = middleware::from_fn(move |req, next| network::select_network(network, req, next))
+        .layer(app);
+    let app = cors.layer(app);
 
-    let addr = SocketAddr::from_str(&format!("{}:{}", host, port))
-        .expect("Failed to create socket address");
     tracing::info!("Listening on {}", addr);
-    axum::serve(tokio::net::TcpListener::bind(&addr).await.unwrap(), app)
-        .await
-        .unwrap();
+    axum::serve(
+        tokio::net::TcpListener::bind(&addr).await.unwrap(),
+        ServiceExt::<Request>::into_make_service(app),
+    )
+    .await
+    .unwrap();
 }
@@ -1,37 +1,334 @@
 // api/src/models/mod.rs
+use crate::error::{WalletError, WalletResult};
+use crate::services::coin_selection::SelectionAlgorithm;
+use crate::services::hd::ScriptKind;
+use bitcoin::{amount::serde::as_sat, Amount, Denomination, SignedAmount};
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Registers a wallet whose keys were generated by the client.
+///
+/// Only account xpubs are sent; the seed, mnemonic and private keys stay
+/// with the client, which signs the PSBTs the API returns.
+#[derive(Debug, Deserialize)]
+pub struct CreateWalletRequest {
+    /// At most one account per script type.
+    pub accounts: Vec<AccountKey>,
+    /// Type of the returned `address`; P2WPKH unless set.
+    #[serde(default)]
+    pub address_type: ScriptKind,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AccountKey {
+    pub script_type: ScriptKind,
+    /// Account-level xpub, e.g. at `m/84'/1'/0'`.
+    pub xpub: String,
+    /// `fingerprint/path` of the xpub below the master key, e.g. `d34db33f/84'/1'/0'`.
+    pub origin: Option<String>,
+}
 
 #[derive(Debug, Serialize)]
-pub struct KeyPair {
-    pub public_key: String,
-    pub private_key: String,
+pub struct CreateWalletResponse {
+    /// Name of the wallet on the node.
+    pub wallet_id: String,
+    /// First receive address of the requested type.
     pub address: String,
+    pub address_type: ScriptKind,
+    pub accounts: Vec<AccountResponse>,
 }
 
+#[derive(Debug, Serialize)]
+pub struct AccountResponse {
+    pub script_type: ScriptKind,
+    pub receive_descriptor: String,
+    pub change_descriptor: String,
+    /// First receive address of this account.
+    pub address: String,
+}
+
+/// Imports an existing wallet by its public keys; the node rescans for its history.
 #[derive(Debug, Deserialize)]
-pub struct CreateWalletRequest {
-    pub password: String,
+pub struct ImportWalletRequest {
+    /// Public ranged `wpkh` or `tr` descriptor.
+    pub descriptor: Option<String>,
+    /// Account xpub, imported on its `/0/*` and `/1/*` chains.
+    pub xpub: Option<String>,
+    /// Key origin of `xpub`, as in [`AccountKey::origin`].
+    pub origin: Option<String>,
+    /// Script type of `xpub` addresses, P2WPKH unless set; checked against a descriptor.
+    pub script_type: Option<ScriptKind>,
+    /// Height of the first block that can hold the wallet's transactions.
+    /// The node rescans from here, from genesis unless set.
+    pub birthday_height: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportWalletResponse {
+    pub wallet_id: String,
+    pub descriptors: Vec<String>,
+    /// First address of each imported descriptor.
+    pub addresses: Vec<ImportedAddress>,
+    /// Height the rescan started from. It runs in the background; poll
+    /// `/wallet/{wallet_id}/rescan` for progress.
+    pub rescan_from_height: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RescanStatusResponse {
+    pub wallet_id: String,
+    pub scanning: bool,
+    /// Fraction of the rescan done, while one is running.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub progress: Option<f32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportedAddress {
+    pub address: String,
+    pub script_type: ScriptKind,
+    pub change: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WatchWalletRequest {
+    /// Ranged `wpkh` or `tr` descriptor; `/<0;1>/*` registers receive and change at once.
+    pub descriptor: Option<String>,
+    /// Account xpub, scanned on its `/0/*` and `/1/*` chains.
+    pub xpub: Option<String>,
+    /// Script type of `xpub` addresses.
+    #[serde(default)]
+    pub script_type: ScriptKind,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WatchWalletResponse {
+    pub wallet_id: String,
+    pub descriptors: Vec<String>,
+    /// The configured `watch.gap_limit` the wallet is scanned with.
+    pub gap_limit: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WalletBalanceResponse {
+    pub wallet_id: String,
+    #[serde(with = "as_sat")]
+    pub balance: Amount,
+    #[serde(with = "as_sat")]
+    pub unconfirmed_balance: Amount,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display: Option<BalanceDisplay>,
+    /// Used addresses only.
+    pub addresses: Vec<BalanceResponse>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UtxoResponse {
+    pub txid: String,
+    pub vout: u32,
+    pub address: String,
+    /// Address type such as `p2wpkh` or `p2tr`.
+    pub script_type: String,
+    /// Whether `address` is on a change chain; omitted for single addresses.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change: Option<bool>,
+    /// Value in satoshis.
+    #[serde(with = "as_sat")]
+    pub value: Amount,
+    /// Confirmation height, `None` while unconfirmed.
+    pub height: Option<u32>,
+    /// Zero while unconfirmed.
+    pub confirmations: u32,
+    /// Outputs carrying charms must not be spent as plain funding.
+    pub has_charms: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WalletUtxosResponse {
+    pub wallet_id: String,
+    pub tip_height: u32,
+    pub utxos: Vec<UtxoResponse>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AddressUtxosResponse {
+    pub address: String,
+    pub tip_height: u32,
+    pub utxos: Vec<UtxoResponse>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AddressKind {
+    #[default]
+    Receive,
+    Change,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct AddressesQuery {
+    #[serde(default)]
+    pub kind: AddressKind,
+    #[serde(default)]
+    pub from: u32,
+    pub count: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WalletAddressesResponse {
+    pub wallet_id: String,
+    pub kind: AddressKind,
+    /// One entry per descriptor of the requested kind.
+    pub chains: Vec<ChainAddresses>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChainAddresses {
+    pub descriptor: String,
+    pub script_type: ScriptKind,
+    pub next_unused_index: u32,
+    pub addresses: Vec<DerivedAddress>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DerivedAddress {
+    pub index: u32,
+    pub address: String,
+    pub has_history: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewAddressQuery {
+    #[serde(default, rename = "type")]
+    pub address_type: ScriptKind,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NewAddressResponse {
+    pub wallet_id: String,
+    pub address: String,
+    pub address_type: ScriptKind,
+}
+
+/// A plain BTC send, funded from a wallet's descriptor chains or a single address.
 #[derive(Debug, Deserialize)]
 pub struct TransactionRequest {
-    pub from_address: String,
+    pub wallet_id: Option<String>,
+    pub from_address: Option<String>,
     pub to_address: String,
-    pub amount: f64,
-    pub private_key: String,
+    /// Whole satoshis; fractional values are rejected.
+    #[serde(with = "as_sat")]
+    pub amount: Amount,
+    pub fee_rate_sat_vb: Option<u64>,
+    pub conf_target: Option<u16>,
+    /// Defaults to the wallet's next change address, or back to `from_address`.
+    pub change_address: Option<String>,
 }
 
+/// An unsigned transaction, ready for the client to sign.
 #[derive(Debug, Serialize)]
 pub struct TransactionResponse {
+    /// Final txid; inputs are all segwit, so signing does not change it.
     pub tx_id: String,
-    pub fee: u64,
+    /// Base64 BIP174 PSBT with `witness_utxo` and key origins on every input.
+    pub psbt: String,
+    #[serde(with = "as_sat")]
+    pub fee: Amount,
+    pub fee_rate_sat_vb: u64,
+    pub algorithm: SelectionAlgorithm,
+    /// Spent outpoints as `txid:vout`.
+    pub inputs: Vec<String>,
+    /// `None` when the leftover was too small and went to the fee.
+    pub change: Option<ChangeOutput>,
+    /// Charm-bearing outputs left out of coin selection.
+    pub excluded_charm_utxos: usize,
 }
 
+#[derive(Debug, Serialize)]
+pub struct ChangeOutput {
+    pub address: String,
+    #[serde(with = "as_sat")]
+    pub value: Amount,
+}
+
+/// Selects the unit of the optional human-readable amounts, e.g. `?unit=BTC`.
+#[derive(Debug, Default, Deserialize)]
+pub struct UnitQuery {
+    pub unit: Option<String>,
+}
+
+impl UnitQuery {
+    /// The requested denomination, `None` when no display amounts were asked for.
+    pub fn denomination(&self) -> WalletResult<Option<Denomination>> {
+        self.unit
+            .as_deref()
+            .map(|unit| {
+                Denomination::from_str(unit).map_err(|e| {
+                    WalletError::InvalidAmount(format!("Unknown unit {}: {}", unit, e))
+                })
+            })
+            .transpose()
+    }
+}
+
+/// Balances in satoshis; `display` repeats them in the requested unit.
 #[derive(Debug, Serialize)]
 pub struct BalanceResponse {
     pub address: String,
-    pub balance: f64,
-    pub unconfirmed_balance: f64,
+    #[serde(with = "as_sat")]
+    pub balance: Amount,
+    #[serde(with = "as_sat")]
+    pub unconfirmed_balance: Amount,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display: Option<BalanceDisplay>,
+}
+
+/// Formatted amounts for showing to people, never for arithmetic.
+#[derive(Debug, Serialize)]
+pub struct BalanceDisplay {
+    pub balance: String,
+    pub unconfirmed_balance: String,
+}
+
+impl BalanceDisplay {
+    pub fn new(balance: Amount, unconfirmed_balance: Amount, unit: Denomination) -> Self {
+        Self {
+            balance: balance.display_in(unit).show_denomination().to_string(),
+            unconfirmed_balance: unconfirmed_balance
+                .display_in(unit)
+                .show_denomination()
+                .to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct AddressHistoryResponse {
+    pub address: String,
+    pub tip_height: u32,
+    /// Newest first, unconfirmed transactions before confirmed ones.
+    pub transactions: Vec<HistoryEntry>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HistoryEntry {
+    pub txid: String,
+    /// Value paid to the address.
+    #[serde(with = "as_sat")]
+    pub received: Amount,
+    /// Value of the address's outputs spent by this transaction.
+    #[serde(with = "as_sat")]
+    pub sent: Amount,
+    /// `received - sent`; negative when the address paid out.
+    #[serde(with = "as_sat")]
+    pub net: SignedAmount,
+    /// Total transaction fee, paid by whoever funded the inputs.
+    #[serde(with = "as_sat::opt", skip_serializing_if = "Option::is_none", default)]
+    pub fee: Option<Amount>,
+    pub confirmed: bool,
+    pub height: Option<u32>,
+    /// Zero while unconfirmed.
+    pub confirmations: u32,
 }
 
 #[derive(Debug, Serialize)]
@@ -51,9 +348,95 @@ pub struct BroadcastTxResponse {
     pub txid: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct BroadcastPackageRequest {
+    /// Raw transactions, parents before children (e.g. `[commit_tx, spell_tx]`).
+    pub txs: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PackageTxStatus {
+    Accepted,
+    Rejected,
+    NotSubmitted,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PackageTxResult {
+    pub txid: String,
+    pub status: PackageTxStatus,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BroadcastPackageResponse {
+    /// `submitpackage` or `sequential`.
+    pub method: String,
+    pub success: bool,
+    /// Set when some transactions reached the mempool before a later one
+    /// failed; those cannot be withdrawn.
+    pub partially_broadcast: bool,
+    pub results: Vec<PackageTxResult>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FinalizeRequest {
+    /// Base64 PSBTs. Copies of the same transaction signed by different
+    /// parties are combined.
+    pub psbts: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FinalizeResponse {
+    /// In the order the transactions first appear in the request.
+    pub transactions: Vec<FinalizedTx>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FinalizedTx {
+    pub txid: String,
+    pub tx_hex: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TestAcceptRequest {
+    /// Raw transactions; dependent ones are checked together as a package.
+    pub txs: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TestAcceptResult {
+    pub txid: String,
+    pub allowed: bool,
+    pub reject_reason: Option<String>,
+    /// Typed rejection, e.g. `insufficient_input_value` or `fee_too_low`.
+    pub reject_code: Option<String>,
+    pub vsize: Option<u64>,
+    /// Fee in satoshis.
+    #[serde(with = "as_sat::opt")]
+    pub fees: Option<Amount>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TestAcceptResponse {
+    pub allowed: bool,
+    pub results: Vec<TestAcceptResult>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TransferCharmsRequest {
     pub spell_json: String,
     pub funding_utxo_id: String,
     pub destination_address: String,
+    /// Explicit fee rate for both transactions. Takes precedence over `conf_target`.
+    pub fee_rate_sat_vb: Option<u64>,
+    /// Confirmation target in blocks; defaults to the configured medium target.
+    pub conf_target: Option<u16>,
+    /// Where leftover funding goes. Must be a P2WPKH or P2TR address on the
+    /// configured network.
+    pub change_address: Option<String>,
+    /// Wallet to take the next unused change address from when no
+    /// `change_address` is given.
+    pub wallet_id: Option<String>,
 }
@@ -0,0 +1,84 @@
+// api/src/network/mod.rs
+use crate::error::{WalletError, WalletResult};
+use axum::{
+    extract::Request,
+    http::Uri,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use bitcoin::{Address, Network};
+use std::str::FromStr;
+
+/// Parses a network name, accepting `mainnet` as an alias for `bitcoin`.
+pub fn parse_network(name: &str) -> Option<Network> {
+    match name {
+        "mainnet" => Some(Network::Bitcoin),
+        other => Network::from_str(other).ok(),
+    }
+}
+
+/// Name used in URLs and error messages.
+pub fn network_name(network: Network) -> &'static str {
+    match network {
+        Network::Bitcoin => "mainnet",
+        Network::Testnet => "testnet",
+        Network::Testnet4 => "testnet4",
+        Network::Signet => "signet",
+        Network::Regtest => "regtest",
+    }
+}
+
+/// Public mempool.space Esplora endpoint for `network`, if there is one.
+pub fn default_esplora_url(network: Network) -> Option<&'static str> {
+    match network {
+        Network::Bitcoin => Some("https://mempool.space/api"),
+        Network::Testnet => Some("https://mempool.space/testnet/api"),
+        Network::Testnet4 => Some("https://mempool.space/testnet4/api"),
+        Network::Signet => Some("https://mempool.space/signet/api"),
+        Network::Regtest => None,
+    }
+}
+
+/// Parses `address` and checks that it belongs to `network`.
+pub fn parse_address(address: &str, network: Network) -> WalletResult<Address> {
+    Address::from_str(address)
+        .map_err(|e| WalletError::InvalidAddress(e.to_string()))?
+        .require_network(network)
+        .map_err(|_| {
+            WalletError::InvalidAddress(format!("Not a {} address", network_name(network)))
+        })
+}
+
+/// Accepts an optional network prefix on every route.
+///
+/// Routes can be called either directly (`/wallet/...`) or with a network
+/// prefix (`/testnet4/wallet/...`). The prefix is stripped before routing and
+/// must name the network this instance was started for.
+pub async fn select_network(served: Network, mut req: Request, next: Next) -> Response {
+    let path = req.uri().path().to_string();
+    let mut segments = path.trim_start_matches('/').splitn(2, '/');
+
+    if let (Some(prefix), Some(rest)) = (segments.next(), segments.next()) {
+        if let Some(requested) = parse_network(prefix) {
+            if requested != served {
+                return WalletError::InvalidNetwork(format!(
+                    "This API serves {}, not {}",
+                    network_name(served),
+                    network_name(requested)
+                ))
+                .into_response();
+            }
+
+            let path_and_query = match req.uri().query() {
+                Some(query) => format!("/{}?{}", rest, query),
+                None => format!("/{}", rest),
+            };
+            match Uri::from_str(&path_and_query) {
+                Ok(uri) => *req.uri_mut() = uri,
+                Err(e) => return WalletError::InvalidNetwork(e.to_string()).into_response(),
+            }
+        }
+    }
+
+    next.run(req).await
+}
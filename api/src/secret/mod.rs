@@ -0,0 +1,38 @@
+// api/src/secret/mod.rs
+use serde::Serialize;
+use static_assertions::assert_not_impl_any;
+use std::fmt;
+use zeroize::Zeroize;
+
+/// A value that must never leave the process.
+///
+/// It deliberately implements neither `Serialize` nor `Debug`/`Display`, so
+/// it cannot be put in a response model, a JSON body or a log line without a
+/// compile error; the only way at the value is [`Secret::expose`]. The value
+/// is wiped when dropped.
+pub struct Secret<T: Zeroize>(T);
+
+// Fails to compile if anyone adds one of these impls.
+assert_not_impl_any!(Secret<String>: Serialize, fmt::Debug, fmt::Display);
+
+impl<T: Zeroize> Secret<T> {
+    pub fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Zeroize + Clone> Clone for Secret<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T: Zeroize> Drop for Secret<T> {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
@@ -0,0 +1,140 @@
+// api/src/services/broadcast.rs
+use crate::chain::ChainBackend;
+use crate::error::{WalletError, WalletResult};
+use crate::models::{BroadcastPackageResponse, PackageTxResult, PackageTxStatus};
+use bitcoin::{Transaction, Txid};
+use std::collections::HashSet;
+
+/// Bitcoin Core's limit on the number of transactions in a package.
+pub const MAX_PACKAGE_SIZE: usize = 25;
+
+/// Checks that `txs` is a connected package ordered parents first.
+///
+/// Every transaction after the first must spend an output of an earlier one,
+/// and none may spend a transaction that comes after it.
+pub fn validate_package(txs: &[Transaction]) -> WalletResult<()> {
+    if txs.is_empty() {
+        return Err(WalletError::InvalidTransaction(
+            "Package must contain at least one transaction".to_string(),
+        ));
+    }
+    if txs.len() > MAX_PACKAGE_SIZE {
+        return Err(WalletError::InvalidTransaction(format!(
+            "Package has {} transactions, the limit is {}",
+            txs.len(),
+            MAX_PACKAGE_SIZE
+        )));
+    }
+
+    let txids: Vec<Txid> = txs.iter().map(|tx| tx.compute_txid()).collect();
+    let mut seen = HashSet::new();
+
+    for (index, tx) in txs.iter().enumerate() {
+        if !seen.insert(txids[index]) {
+            return Err(WalletError::InvalidTransaction(format!(
+                "Transaction {} appears more than once",
+                txids[index]
+            )));
+        }
+
+        let spends = |txid: &Txid| {
+            tx.input
+                .iter()
+                .any(|input| input.previous_output.txid == *txid)
+        };
+
+        if let Some(later) = txids[index + 1..].iter().find(|txid| spends(txid)) {
+            return Err(WalletError::InvalidTransaction(format!(
+                "Transaction {} spends {} which comes later in the package",
+                txids[index], later
+            )));
+        }
+        if index > 0 && !txids[..index].iter().any(spends) {
+            return Err(WalletError::InvalidTransaction(format!(
+                "Transaction {} does not spend any earlier transaction in the package",
+                txids[index]
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Broadcasts a validated package, preferring `submitpackage` and falling
+/// back to submitting the transactions in order.
+pub async fn broadcast_package(
+    chain: &dyn ChainBackend,
+    txs: &[Transaction],
+) -> WalletResult<BroadcastPackageResponse> {
+    validate_package(txs)?;
+
+    if let Some(outcomes) = chain.submit_package(txs).await? {
+        let results: Vec<PackageTxResult> = outcomes
+            .into_iter()
+            .map(|outcome| PackageTxResult {
+                txid: outcome.txid.to_string(),
+                status: if outcome.error.is_none() {
+                    PackageTxStatus::Accepted
+                } else {
+                    PackageTxStatus::Rejected
+                },
+                error: outcome.error,
+            })
+            .collect();
+
+        return Ok(package_response("submitpackage", results));
+    }
+
+    tracing::info!(
+        "{} backend has no package relay, broadcasting sequentially",
+        chain.name()
+    );
+
+    let mut results = Vec::with_capacity(txs.len());
+    let mut failed = false;
+
+    for tx in txs {
+        let txid = tx.compute_txid().to_string();
+        if failed {
+            results.push(PackageTxResult {
+                txid,
+                status: PackageTxStatus::NotSubmitted,
+                error: None,
+            });
+            continue;
+        }
+
+        match chain.broadcast(tx).await {
+            Ok(_) => results.push(PackageTxResult {
+                txid,
+                status: PackageTxStatus::Accepted,
+                error: None,
+            }),
+            Err(e) => {
+                tracing::error!("Package transaction {} rejected: {}", txid, e);
+                failed = true;
+                results.push(PackageTxResult {
+                    txid,
+                    status: PackageTxStatus::Rejected,
+                    error: Some(e.to_string()),
+                });
+            }
+        }
+    }
+
+    Ok(package_response("sequential", results))
+}
+
+fn package_response(method: &str, results: Vec<PackageTxResult>) -> BroadcastPackageResponse {
+    let accepted = results
+        .iter()
+        .filter(|r| r.status == PackageTxStatus::Accepted)
+        .count();
+
+    BroadcastPackageResponse {
+        method: method.to_string(),
+        success: accepted == results.len(),
+        partially_broadcast: accepted > 0 && accepted < results.len(),
+        results,
+    }
+}
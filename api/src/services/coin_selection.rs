@@ -0,0 +1,380 @@
+// api/src/services/coin_selection.rs
+use crate::error::{WalletError, WalletResult};
+use bitcoin::{Amount, FeeRate, Weight};
+use rand::{thread_rng, Rng};
+use serde::Serialize;
+
+/// Branch-and-bound gives up after exploring this many branches.
+const BNB_MAX_TRIES: usize = 100_000;
+
+/// Random passes of the knapsack approximation.
+const KNAPSACK_ITERATIONS: usize = 1_000;
+
+/// A spendable output as coin selection sees it.
+#[derive(Debug, Clone, Copy)]
+pub struct Candidate {
+    pub value: Amount,
+    /// Weight the input adds to the transaction, witness included.
+    pub input_weight: Weight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SelectionAlgorithm {
+    /// Changeless match found by branch-and-bound.
+    BranchAndBound,
+    /// Approximate subset sum leaving room for a change output.
+    Knapsack,
+}
+
+#[derive(Debug, Clone)]
+pub struct Selection {
+    /// Indices into the candidate list.
+    pub inputs: Vec<usize>,
+    pub algorithm: SelectionAlgorithm,
+}
+
+/// Chooses candidates to fund `target`, which must already include the fee
+/// for the transaction's fixed parts and recipient outputs.
+///
+/// Works on effective values, i.e. each candidate's value minus the fee to
+/// spend it at `fee_rate`; candidates worth less than that are never picked.
+/// Branch-and-bound first looks for a selection that needs no change,
+/// overshooting by at most `cost_of_change`. Failing that, a knapsack search
+/// aims for `target + change_target` so the leftover can pay for a change
+/// output.
+///
+/// Among changeless selections, branch-and-bound keeps the one with the
+/// least overshoot. Unlike Bitcoin Core it does not score selections by
+/// waste, so it neither weighs the number of inputs nor compares the fee
+/// rate with a long-term rate to consolidate or save inputs.
+pub fn select_coins(
+    candidates: &[Candidate],
+    target: Amount,
+    fee_rate: FeeRate,
+    cost_of_change: Amount,
+    change_target: Amount,
+) -> WalletResult<Selection> {
+    let mut pool: Vec<(usize, u64)> = candidates
+        .iter()
+        .enumerate()
+        .filter_map(|(index, candidate)| {
+            let spend_fee = fee_rate.fee_wu(candidate.input_weight)?;
+            let effective = candidate.value.checked_sub(spend_fee)?;
+            (effective > Amount::ZERO).then_some((index, effective.to_sat()))
+        })
+        .collect();
+    pool.sort_by_key(|&(_, value)| std::cmp::Reverse(value));
+
+    let available: u64 = pool.iter().map(|(_, value)| value).sum();
+    if available < target.to_sat() {
+        return Err(WalletError::InsufficientFunds(format!(
+            "{} sats spendable at this fee rate, {} sats needed",
+            available,
+            target.to_sat()
+        )));
+    }
+
+    let values: Vec<u64> = pool.iter().map(|(_, value)| *value).collect();
+    let to_candidates =
+        |picked: Vec<usize>| -> Vec<usize> { picked.into_iter().map(|i| pool[i].0).collect() };
+
+    if let Some(picked) = branch_and_bound(
+        &values,
+        target.to_sat(),
+        cost_of_change.to_sat(),
+        BNB_MAX_TRIES,
+    ) {
+        return Ok(Selection {
+            inputs: to_candidates(picked),
+            algorithm: SelectionAlgorithm::BranchAndBound,
+        });
+    }
+
+    // With change, aim for the target plus room for the change output; if
+    // that is out of reach, any selection covering the bare target will do
+    // and the leftover goes to the fee.
+    let picked = knapsack(&values, target.to_sat() + change_target.to_sat())
+        .or_else(|| knapsack(&values, target.to_sat()))
+        .ok_or_else(|| {
+            WalletError::InsufficientFunds(format!(
+                "no selection of the {} sats spendable reaches {} sats",
+                available,
+                target.to_sat()
+            ))
+        })?;
+    Ok(Selection {
+        inputs: to_candidates(picked),
+        algorithm: SelectionAlgorithm::Knapsack,
+    })
+}
+
+/// Depth-first search over `values` (sorted descending) for a subset summing
+/// to between `target` and `target + cost_of_change`, preferring the least
+/// overshoot. Each step either includes the next value or omits it; the
+/// search stops after `max_tries` steps with the best subset found so far.
+fn branch_and_bound(
+    values: &[u64],
+    target: u64,
+    cost_of_change: u64,
+    max_tries: usize,
+) -> Option<Vec<usize>> {
+    let upper = target + cost_of_change;
+    let mut remaining: u64 = values.iter().sum();
+    let mut current_value = 0;
+    let mut current: Vec<usize> = Vec::new();
+    let mut best: Option<(u64, Vec<usize>)> = None;
+    let mut index = 0;
+
+    for _ in 0..max_tries {
+        let backtrack = if current_value + remaining < target || current_value > upper {
+            true
+        } else if current_value >= target {
+            let overshoot = current_value - target;
+            if best
+                .as_ref()
+                .is_none_or(|(best_overshoot, _)| overshoot <= *best_overshoot)
+            {
+                best = Some((overshoot, current.clone()));
+            }
+            true
+        } else {
+            false
+        };
+
+        if backtrack {
+            let Some(&last) = current.last() else {
+                break;
+            };
+            // Give back the values omitted after the last inclusion, then
+            // explore the branch that omits it.
+            index -= 1;
+            while index > last {
+                remaining += values[index];
+                index -= 1;
+            }
+            current_value -= values[index];
+            current.pop();
+        } else {
+            remaining -= values[index];
+            // Omitting a value and then including an equal one repeats a
+            // branch already explored.
+            if current.is_empty()
+                || current.last() == Some(&(index - 1))
+                || values[index] != values[index - 1]
+            {
+                current.push(index);
+                current_value += values[index];
+            }
+        }
+        index += 1;
+    }
+
+    best.map(|(_, picked)| picked)
+}
+
+/// Bitcoin Core's knapsack: an exact match if there is one, otherwise the
+/// better of the smallest single value above `target` and the closest
+/// random subset of the smaller values.
+fn knapsack(values: &[u64], target: u64) -> Option<Vec<usize>> {
+    if let Some(exact) = values.iter().position(|&value| value == target) {
+        return Some(vec![exact]);
+    }
+
+    // `values` is sorted descending, so the last larger value is the smallest.
+    let lowest_larger = values.iter().rposition(|&value| value > target);
+    let smaller: Vec<usize> = (0..values.len()).filter(|&i| values[i] < target).collect();
+    let smaller_total: u64 = smaller.iter().map(|&i| values[i]).sum();
+
+    if smaller_total == target {
+        return Some(smaller);
+    }
+    if smaller_total < target {
+        return lowest_larger.map(|i| vec![i]);
+    }
+
+    let smaller_values: Vec<u64> = smaller.iter().map(|&i| values[i]).collect();
+    let (subset_total, included) = approximate_best_subset(&smaller_values, smaller_total, target);
+    match lowest_larger {
+        Some(i) if subset_total != target && values[i] <= subset_total => Some(vec![i]),
+        _ => Some(
+            smaller
+                .into_iter()
+                .zip(included)
+                .filter_map(|(i, included)| included.then_some(i))
+                .collect(),
+        ),
+    }
+}
+
+/// Randomised search for the subset of `values` closest to, but not below,
+/// `target`. Starts from the full set, whose sum is `total`.
+fn approximate_best_subset(values: &[u64], total: u64, target: u64) -> (u64, Vec<bool>) {
+    let mut rng = thread_rng();
+    let mut best = vec![true; values.len()];
+    let mut best_total = total;
+
+    for _ in 0..KNAPSACK_ITERATIONS {
+        if best_total == target {
+            break;
+        }
+        let mut included = vec![false; values.len()];
+        let mut running = 0;
+        let mut reached = false;
+
+        // First pass includes values at random, the second fills in the rest.
+        for pass in 0..2 {
+            if reached {
+                break;
+            }
+            for i in 0..values.len() {
+                let take = if pass == 0 {
+                    rng.gen_bool(0.5)
+                } else {
+                    !included[i]
+                };
+                if !take {
+                    continue;
+                }
+                running += values[i];
+                included[i] = true;
+                if running >= target {
+                    reached = true;
+                    if running < best_total {
+                        best_total = running;
+                        best = included.clone();
+                    }
+                    running -= values[i];
+                    included[i] = false;
+                }
+            }
+        }
+    }
+
+    (best_total, best)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Weight of a P2WPKH input.
+    const P2WPKH_INPUT: Weight = Weight::from_wu(272);
+
+    fn candidates(values: &[u64]) -> Vec<Candidate> {
+        values
+            .iter()
+            .map(|&value| Candidate {
+                value: Amount::from_sat(value),
+                input_weight: P2WPKH_INPUT,
+            })
+            .collect()
+    }
+
+    fn total(candidates: &[Candidate], selection: &Selection) -> u64 {
+        selection
+            .inputs
+            .iter()
+            .map(|&i| candidates[i].value.to_sat())
+            .sum()
+    }
+
+    #[test]
+    fn exact_match_needs_no_change() {
+        let pool = candidates(&[20_000, 50_000, 30_000]);
+        let selection = select_coins(
+            &pool,
+            Amount::from_sat(80_000),
+            FeeRate::ZERO,
+            Amount::from_sat(500),
+            Amount::from_sat(5_000),
+        )
+        .unwrap();
+
+        assert_eq!(selection.algorithm, SelectionAlgorithm::BranchAndBound);
+        let mut inputs = selection.inputs.clone();
+        inputs.sort();
+        assert_eq!(inputs, vec![1, 2]);
+    }
+
+    #[test]
+    fn falls_back_to_knapsack_with_change() {
+        let pool = candidates(&[50_000, 30_000, 20_000]);
+        let selection = select_coins(
+            &pool,
+            Amount::from_sat(60_000),
+            FeeRate::ZERO,
+            Amount::from_sat(1_000),
+            Amount::from_sat(5_000),
+        )
+        .unwrap();
+
+        assert_eq!(selection.algorithm, SelectionAlgorithm::Knapsack);
+        assert!(total(&pool, &selection) >= 65_000);
+    }
+
+    #[test]
+    fn insufficient_funds() {
+        let pool = candidates(&[10_000, 20_000]);
+        let result = select_coins(
+            &pool,
+            Amount::from_sat(30_001),
+            FeeRate::ZERO,
+            Amount::from_sat(500),
+            Amount::from_sat(5_000),
+        );
+
+        assert!(matches!(result, Err(WalletError::InsufficientFunds(_))));
+    }
+
+    #[test]
+    fn drops_inputs_worth_less_than_their_fee() {
+        // 272 wu at 10 sat/vB costs 680 sats to spend.
+        let fee_rate = FeeRate::from_sat_per_vb(10).unwrap();
+        let pool = candidates(&[500, 100_000]);
+        let selection = select_coins(
+            &pool,
+            Amount::from_sat(50_000),
+            fee_rate,
+            Amount::from_sat(500),
+            Amount::from_sat(5_000),
+        )
+        .unwrap();
+        assert_eq!(selection.inputs, vec![1]);
+
+        let result = select_coins(
+            &candidates(&[500, 600]),
+            Amount::from_sat(1),
+            fee_rate,
+            Amount::from_sat(500),
+            Amount::from_sat(5_000),
+        );
+        assert!(matches!(result, Err(WalletError::InsufficientFunds(_))));
+    }
+
+    #[test]
+    fn skips_equal_value_branches() {
+        // 97 = nine 10s + 7, but the search first tries every way to add 10s
+        // and 7 to 51, none of which works. Counting each choice of equal
+        // 10s separately would take far more than the tries allowed.
+        let mut values = vec![51];
+        values.extend([10; 20]);
+        values.push(7);
+
+        let picked = branch_and_bound(&values, 97, 0, 1_000).unwrap();
+        let mut picked: Vec<u64> = picked.iter().map(|&i| values[i]).collect();
+        picked.sort();
+        assert_eq!(picked, [vec![7], vec![10; 9]].concat());
+    }
+
+    #[test]
+    fn stops_after_max_tries() {
+        // Even values can never sum to an odd target, and 40 of them are far
+        // too many to search exhaustively.
+        let mut values: Vec<u64> = (0..40).map(|i| 2 * (1_000 + 37 * i)).collect();
+        values.reverse();
+        let target = (values.iter().sum::<u64>() / 2) | 1;
+
+        assert_eq!(branch_and_bound(&values, target, 0, BNB_MAX_TRIES), None);
+    }
+}
@@ -0,0 +1,452 @@
+// api/src/services/commit.rs
+use crate::error::{WalletError, WalletResult};
+use crate::secret::Secret;
+use crate::services::hd::ScriptKind;
+use crate::services::psbt::tapscript_spend;
+use crate::services::send::{fee_for, input_weight, INPUT_BASE_WU, SEGWIT_HEADER_WU};
+use bitcoin::{
+    absolute::LockTime,
+    consensus::encode::VarInt,
+    key::Keypair,
+    opcodes::all::{OP_ENDIF, OP_IF},
+    script::Instruction,
+    secp256k1::{All, Message, Secp256k1},
+    sighash::{Prevouts, SighashCache, TapSighash, TapSighashType},
+    taproot::{self, ControlBlock, LeafVersion, TapLeafHash},
+    transaction::Version,
+    Amount, FeeRate, OutPoint, Script, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Weight,
+    Witness, XOnlyPublicKey,
+};
+use rand::thread_rng;
+use std::collections::BTreeMap;
+
+/// Sighash of the ephemeral key's signature: it covers every output but only
+/// its own input, so the user's inputs can still be signed afterwards.
+const COMMIT_SIGHASH: TapSighashType = TapSighashType::AllPlusAnyoneCanPay;
+
+/// The commit and spell transactions of one spell.
+pub struct SpellTxs {
+    pub commit_tx: Transaction,
+    /// Spends the spell's inputs and the commit output, which is already signed.
+    pub spell_tx: Transaction,
+    /// Index of the spell tx input spending the commit output.
+    pub commit_input: usize,
+}
+
+impl SpellTxs {
+    /// Spell tx inputs left for the user to sign. The commit tx's only
+    /// input, the funding UTXO, is always theirs as well.
+    pub fn user_inputs(&self) -> Vec<usize> {
+        (0..self.spell_tx.input.len())
+            .filter(|&index| index != self.commit_input)
+            .collect()
+    }
+}
+
+/// One-off key locking the commit output to the spell's tapscript leaf.
+///
+/// It is generated for a single spell and never stored or returned:
+/// [`EphemeralKey::sign`] consumes it for the one signature the spell tx
+/// needs, and the secret is wiped on drop.
+struct EphemeralKey {
+    secret: Secret<[u8; 32]>,
+    public: XOnlyPublicKey,
+}
+
+impl EphemeralKey {
+    fn generate(secp: &Secp256k1<All>) -> Self {
+        let (mut secret_key, public_key) = secp.generate_keypair(&mut thread_rng());
+        let secret = Secret::new(secret_key.secret_bytes());
+        secret_key.non_secure_erase();
+        Self {
+            secret,
+            public: public_key.x_only_public_key().0,
+        }
+    }
+
+    fn sign(self, secp: &Secp256k1<All>, sighash: TapSighash) -> WalletResult<taproot::Signature> {
+        let mut keypair = Keypair::from_seckey_slice(secp, self.secret.expose())
+            .map_err(|e| WalletError::InvalidKey(e.to_string()))?;
+        let signature = secp.sign_schnorr(&Message::from(sighash), &keypair);
+        keypair.non_secure_erase();
+        Ok(taproot::Signature {
+            signature,
+            sighash_type: COMMIT_SIGHASH,
+        })
+    }
+}
+
+/// Builds the commit/spell pair for `tx`, the spell's unsigned transaction.
+///
+/// The commit tx moves the funding UTXO into a taproot output whose only
+/// leaf carries `spell_data` and is locked to a fresh ephemeral key. The
+/// spell tx appends an input spending that output, signed here with the
+/// ephemeral key, and sends what is left after fees to `change_script`.
+///
+/// The funding UTXO and the spell's own inputs must be segwit: the pair
+/// refers to the commit tx by txid, which must not change when the user
+/// signs.
+pub fn build(
+    tx: Transaction,
+    spell_data: &[u8],
+    funding_utxo: OutPoint,
+    funding_output: &TxOut,
+    change_script: ScriptBuf,
+    fee_rate: FeeRate,
+    prev_txs: &BTreeMap<Txid, Transaction>,
+) -> WalletResult<SpellTxs> {
+    let secp = Secp256k1::new();
+    let key = EphemeralKey::generate(&secp);
+
+    let script = charms::script::data_script(key.public, spell_data);
+    let control_block = charms::script::control_block(key.public, script.clone());
+    let leaf_hash = TapLeafHash::from_script(&script, LeafVersion::TapScript);
+
+    let funding_kind = segwit_kind(&funding_output.script_pubkey, "funding UTXO")?;
+    let mut commit_tx = Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![unsigned_input(funding_utxo)],
+        output: vec![TxOut {
+            value: Amount::ZERO,
+            script_pubkey: ScriptBuf::new_p2tr(&secp, key.public, Some(leaf_hash.into())),
+        }],
+    };
+    let commit_weight =
+        commit_tx.weight() + Weight::from_wu(SEGWIT_HEADER_WU) + input_weight(funding_kind)
+            - Weight::from_wu(INPUT_BASE_WU);
+    let commit_value = funding_output
+        .value
+        .checked_sub(fee_for(fee_rate, commit_weight)?)
+        .filter(|value| *value >= commit_tx.output[0].script_pubkey.minimal_non_dust())
+        .ok_or_else(|| {
+            WalletError::InvalidAmount(format!(
+                "Funding UTXO of {} sats cannot pay the commit fee",
+                funding_output.value.to_sat()
+            ))
+        })?;
+    commit_tx.output[0].value = commit_value;
+    let commit_output = commit_tx.output[0].clone();
+
+    let mut spell_tx = tx;
+    let mut input_total = commit_value;
+    let mut witness_weight = commit_witness_weight(&script, &control_block);
+    for (index, tx_in) in spell_tx.input.iter().enumerate() {
+        let prevout = tx_in.previous_output;
+        let spent = prev_txs
+            .get(&prevout.txid)
+            .and_then(|tx| tx.output.get(prevout.vout as usize))
+            .ok_or_else(|| {
+                WalletError::InvalidTransaction(format!(
+                    "Missing previous output {} for input {}",
+                    prevout, index
+                ))
+            })?;
+        let kind = segwit_kind(&spent.script_pubkey, "spell input")?;
+        witness_weight += input_weight(kind) - Weight::from_wu(INPUT_BASE_WU);
+        input_total += spent.value;
+    }
+
+    let commit_input = spell_tx.input.len();
+    spell_tx
+        .input
+        .push(unsigned_input(OutPoint::new(commit_tx.compute_txid(), 0)));
+    spell_tx.output.push(TxOut {
+        value: Amount::ZERO,
+        script_pubkey: change_script,
+    });
+    let spell_weight = spell_tx.weight() + Weight::from_wu(SEGWIT_HEADER_WU) + witness_weight;
+    let output_total: Amount = spell_tx.output.iter().map(|out| out.value).sum();
+    let change = input_total
+        .checked_sub(output_total + fee_for(fee_rate, spell_weight)?)
+        .ok_or_else(|| {
+            WalletError::InvalidAmount(format!(
+                "Funding UTXO of {} sats cannot pay the fees of both transactions",
+                funding_output.value.to_sat()
+            ))
+        })?;
+    // Change too small to relay goes to the fee instead.
+    match spell_tx.output.last_mut() {
+        Some(out) if change >= out.script_pubkey.minimal_non_dust() => out.value = change,
+        _ => {
+            spell_tx.output.pop();
+        }
+    }
+
+    // Outputs are final from here on; the signature commits to all of them.
+    let sighash = SighashCache::new(&spell_tx)
+        .taproot_script_spend_signature_hash(
+            commit_input,
+            &Prevouts::One(commit_input, commit_output),
+            leaf_hash,
+            COMMIT_SIGHASH,
+        )
+        .map_err(|e| WalletError::InvalidTransaction(format!("Cannot sign spell input: {}", e)))?;
+    let signature = key.sign(&secp, sighash)?;
+
+    let witness = &mut spell_tx.input[commit_input].witness;
+    witness.push(signature.to_vec());
+    witness.push(script.as_bytes());
+    witness.push(control_block.serialize());
+
+    Ok(SpellTxs {
+        commit_tx,
+        spell_tx,
+        commit_input,
+    })
+}
+
+/// Spell data, proof included, from a commit/spell pair built elsewhere,
+/// e.g. by the prover.
+///
+/// The data is read from the pushes in the envelope of the leaf the spell
+/// tx's commit input reveals. It is only accepted if `data_script` rebuilds
+/// exactly that leaf from it and the leaf's key.
+pub fn spell_data(commit_tx: &Transaction, spell_tx: &Transaction) -> WalletResult<Vec<u8>> {
+    let commit_txid = commit_tx.compute_txid();
+    let witness = spell_tx
+        .input
+        .iter()
+        .find(|input| input.previous_output.txid == commit_txid)
+        .map(|input| &input.witness)
+        .ok_or_else(|| {
+            WalletError::InvalidTransaction("Spell tx does not spend the commit tx".to_string())
+        })?;
+    let (script, control_block) = tapscript_spend(witness)?;
+
+    let mut pushes = Vec::new();
+    let mut in_envelope = false;
+    for instruction in script.instructions() {
+        match instruction
+            .map_err(|_| WalletError::InvalidTransaction("Malformed spell leaf".to_string()))?
+        {
+            Instruction::Op(OP_IF) => in_envelope = true,
+            Instruction::Op(OP_ENDIF) => break,
+            Instruction::PushBytes(bytes) if in_envelope => pushes.push(bytes.as_bytes()),
+            _ => {}
+        }
+    }
+
+    // The envelope may open with a marker push ahead of the data.
+    (0..pushes.len().min(2))
+        .map(|skip| pushes[skip..].concat())
+        .find(|data| charms::script::data_script(control_block.internal_key, data) == script)
+        .ok_or_else(|| {
+            WalletError::InvalidTransaction("Spell leaf does not carry spell data".to_string())
+        })
+}
+
+/// Checks that `spell_tx` carries the spell `proven_spell_tx` was proven
+/// with, as charms itself reads and verifies it.
+///
+/// [`spell_data`] reads the envelope by hand; this catches it picking the
+/// wrong pushes, or the rebuilt pair losing the proof.
+pub fn verify_spell(proven_spell_tx: &Transaction, spell_tx: &Transaction) -> WalletResult<()> {
+    let proven = charms::tx::norm_spell(proven_spell_tx).ok_or_else(|| {
+        WalletError::InvalidTransaction("Prover output carries no valid spell".to_string())
+    })?;
+    let rebuilt = charms::tx::norm_spell(spell_tx).ok_or_else(|| {
+        WalletError::InvalidTransaction("Spell tx carries no valid spell".to_string())
+    })?;
+    let encode = |spell| {
+        serde_json::to_value(spell)
+            .map_err(|e| WalletError::InvalidTransaction(format!("Cannot encode spell: {}", e)))
+    };
+    if encode(&proven)? != encode(&rebuilt)? {
+        return Err(WalletError::InvalidTransaction(
+            "Spell tx does not carry the proven spell".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn unsigned_input(previous_output: OutPoint) -> TxIn {
+    TxIn {
+        previous_output,
+        script_sig: ScriptBuf::new(),
+        sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+        witness: Witness::new(),
+    }
+}
+
+/// Witness of the commit input: a Schnorr signature with its sighash byte,
+/// the leaf script and the control block.
+fn commit_witness_weight(script: &Script, control_block: &ControlBlock) -> Weight {
+    let control_block_len = control_block.serialize().len();
+    let witness = 1
+        + (1 + 65)
+        + VarInt::from(script.len()).size()
+        + script.len()
+        + VarInt::from(control_block_len).size()
+        + control_block_len;
+    Weight::from_wu(witness as u64)
+}
+
+fn segwit_kind(script_pubkey: &Script, what: &str) -> WalletResult<ScriptKind> {
+    ScriptKind::of_script(script_pubkey).ok_or_else(|| {
+        WalletError::InvalidTransaction(format!("The {} must be P2WPKH or P2TR", what))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{hashes::Hash, secp256k1::SecretKey, CompressedPublicKey};
+
+    const FEE_RATE: FeeRate = FeeRate::from_sat_per_vb_u32(3);
+
+    fn p2wpkh(seed: u8) -> ScriptBuf {
+        let secp = Secp256k1::new();
+        let key = SecretKey::from_slice(&[seed; 32])
+            .unwrap()
+            .public_key(&secp);
+        ScriptBuf::new_p2wpkh(&CompressedPublicKey(key).wpubkey_hash())
+    }
+
+    fn p2tr(seed: u8) -> ScriptBuf {
+        let secp = Secp256k1::new();
+        let key = SecretKey::from_slice(&[seed; 32])
+            .unwrap()
+            .x_only_public_key(&secp)
+            .0;
+        ScriptBuf::new_p2tr(&secp, key, None)
+    }
+
+    /// A spell tx spending a P2WPKH and a P2TR output of one previous tx,
+    /// funded by a separate P2WPKH UTXO.
+    fn fixture() -> (SpellTxs, Vec<u8>, TxOut) {
+        let prev_tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![unsigned_input(OutPoint::new(Txid::all_zeros(), 0))],
+            output: vec![
+                TxOut {
+                    value: Amount::from_sat(1_000),
+                    script_pubkey: p2wpkh(1),
+                },
+                TxOut {
+                    value: Amount::from_sat(2_000),
+                    script_pubkey: p2tr(2),
+                },
+            ],
+        };
+        let prev_txid = prev_tx.compute_txid();
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![
+                unsigned_input(OutPoint::new(prev_txid, 0)),
+                unsigned_input(OutPoint::new(prev_txid, 1)),
+            ],
+            output: vec![TxOut {
+                value: Amount::from_sat(1_000),
+                script_pubkey: p2tr(3),
+            }],
+        };
+        let funding_output = TxOut {
+            value: Amount::from_sat(50_000),
+            script_pubkey: p2wpkh(4),
+        };
+        let spell_data: Vec<u8> = (0..1_500u32).map(|i| i as u8).collect();
+        let pair = build(
+            tx,
+            &spell_data,
+            OutPoint::new(Txid::all_zeros(), 1),
+            &funding_output,
+            p2wpkh(5),
+            FEE_RATE,
+            &BTreeMap::from([(prev_txid, prev_tx)]),
+        )
+        .unwrap();
+        (pair, spell_data, funding_output)
+    }
+
+    #[test]
+    fn commit_input_signature_verifies() {
+        let (pair, spell_data, _) = fixture();
+        let secp = Secp256k1::new();
+        let witness = &pair.spell_tx.input[pair.commit_input].witness;
+        let (script, control_block) = tapscript_spend(witness).unwrap();
+        assert_eq!(
+            script,
+            charms::script::data_script(control_block.internal_key, &spell_data)
+        );
+
+        let signature = taproot::Signature::from_slice(&witness[0]).unwrap();
+        assert_eq!(signature.sighash_type, TapSighashType::AllPlusAnyoneCanPay);
+        let sighash = SighashCache::new(&pair.spell_tx)
+            .taproot_script_spend_signature_hash(
+                pair.commit_input,
+                &Prevouts::One(pair.commit_input, pair.commit_tx.output[0].clone()),
+                TapLeafHash::from_script(&script, LeafVersion::TapScript),
+                signature.sighash_type,
+            )
+            .unwrap();
+        secp.verify_schnorr(
+            &signature.signature,
+            &Message::from(sighash),
+            &control_block.internal_key,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn control_block_matches_commit_output() {
+        let (pair, _, _) = fixture();
+        let secp = Secp256k1::new();
+        let witness = &pair.spell_tx.input[pair.commit_input].witness;
+        let (script, control_block) = tapscript_spend(witness).unwrap();
+
+        let output_key = pair.commit_tx.output[0]
+            .script_pubkey
+            .as_bytes()
+            .get(2..)
+            .and_then(|key| XOnlyPublicKey::from_slice(key).ok())
+            .unwrap();
+        assert!(control_block.verify_taproot_commitment(&secp, output_key, &script));
+        assert_eq!(
+            pair.spell_tx.input[pair.commit_input].previous_output,
+            OutPoint::new(pair.commit_tx.compute_txid(), 0)
+        );
+    }
+
+    #[test]
+    fn fees_and_change_match_fee_rate() {
+        let (pair, _, funding_output) = fixture();
+
+        let commit_weight = pair.commit_tx.weight()
+            + Weight::from_wu(SEGWIT_HEADER_WU)
+            + input_weight(ScriptKind::P2wpkh)
+            - Weight::from_wu(INPUT_BASE_WU);
+        assert_eq!(
+            funding_output.value - pair.commit_tx.output[0].value,
+            fee_for(FEE_RATE, commit_weight).unwrap()
+        );
+
+        // The commit input is signed already. The user's inputs only carry
+        // an empty witness so far and still need a P2WPKH signature and key
+        // and a P2TR signature.
+        let spell_weight = pair.spell_tx.weight() + Weight::from_wu((1 + 72) + (1 + 33) + (1 + 64));
+        let input_total = Amount::from_sat(1_000 + 2_000) + pair.commit_tx.output[0].value;
+        let output_total: Amount = pair.spell_tx.output.iter().map(|out| out.value).sum();
+        assert_eq!(
+            input_total - output_total,
+            fee_for(FEE_RATE, spell_weight).unwrap()
+        );
+
+        let change = pair.spell_tx.output.last().unwrap();
+        assert_eq!(pair.spell_tx.output.len(), 2);
+        assert_eq!(change.script_pubkey, p2wpkh(5));
+        assert_eq!(
+            change.value,
+            input_total - Amount::from_sat(1_000) - fee_for(FEE_RATE, spell_weight).unwrap()
+        );
+    }
+
+    #[test]
+    fn user_inputs_exclude_commit_input() {
+        let (pair, _, _) = fixture();
+        assert_eq!(pair.commit_input, 2);
+        assert_eq!(pair.user_inputs(), vec![0, 1]);
+    }
+}
@@ -1,102 +1,426 @@
 // api/src/services/external.rs
+use crate::chain::{ChainBackend, Utxo};
 use crate::error::{WalletError, WalletResult};
 use crate::models::*;
-use bitcoin::{consensus::deserialize, hashes::hex::FromHex, Address, Network, Transaction, Txid};
-use bitcoincore_rpc::{Auth, Client as RpcClient, RpcApi};
-use reqwest::Client;
-use serde_json::Value;
-use std::str::FromStr;
+use crate::network::parse_address;
+use crate::services::hd::{DescriptorChain, ScriptKind};
+use bitcoin::bip32::KeySource;
+use bitcoin::secp256k1::{PublicKey, Secp256k1, VerifyOnly};
+use bitcoin::{Address, Amount, Denomination, Network, OutPoint, SignedAmount, TxOut, Txid};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::Arc,
+};
+
+/// An address found by a wallet scan, with the outputs it currently holds.
+struct ScannedAddress {
+    address: Address,
+    change: bool,
+    /// Position of the address's chain in the scanned list, and its index on it.
+    chain: usize,
+    index: u32,
+    utxos: Vec<Utxo>,
+}
+
+/// An unspent output the caller controls, with what coin selection and a
+/// PSBT signer need to know about it.
+pub struct OwnedUtxo {
+    pub utxo: Utxo,
+    pub txout: TxOut,
+    /// `None` for script types the wallet cannot estimate or sign for.
+    pub kind: Option<ScriptKind>,
+    pub has_charms: bool,
+    /// Public key and its derivation, known for outputs found on a descriptor chain.
+    pub key_source: Option<(PublicKey, KeySource)>,
+}
 
 pub struct ExternalWalletService {
     network: Network,
-    client: Client,
+    chain: Arc<dyn ChainBackend>,
+    secp: Secp256k1<VerifyOnly>,
+    gap_limit: u32,
 }
 
 impl ExternalWalletService {
-    pub fn new() -> Self {
+    pub fn new(network: Network, chain: Arc<dyn ChainBackend>, gap_limit: u32) -> Self {
         Self {
-            network: Network::Testnet,
-            client: Client::new(),
+            network,
+            chain,
+            secp: Secp256k1::verification_only(),
+            gap_limit,
         }
     }
 
-    pub async fn get_balance(&self, address: &str) -> WalletResult<BalanceResponse> {
+    pub async fn get_balance(
+        &self,
+        address: &str,
+        unit: Option<Denomination>,
+    ) -> WalletResult<BalanceResponse> {
         tracing::info!("Getting balance for address: {}", address);
 
-        let addr =
-            Address::from_str(address).map_err(|e| WalletError::InvalidAddress(e.to_string()))?;
+        let addr = parse_address(address, self.network)?;
 
-        addr.require_network(self.network)
-            .map_err(|_| WalletError::InvalidAddress("Not a testnet4 address".to_string()))?;
+        tracing::info!("Fetching UTXOs from {} backend", self.chain.name());
+        let utxos = self.chain.get_utxos(&addr).await?;
 
-        let utxo_url = format!(
-            "https://mempool.space/testnet4/api/address/{}/utxo",
-            address
-        );
-        tracing::info!("Fetching UTXOs from: {}", utxo_url);
-
-        let response = self
-            .client
-            .get(&utxo_url)
-            .send()
-            .await
-            .map_err(|e| WalletError::NetworkError(e.to_string()))?;
-
-        let response_text = response
-            .text()
-            .await
-            .map_err(|e| WalletError::NetworkError(e.to_string()))?;
-
-        let utxos: Vec<Value> = serde_json::from_str(&response_text)
-            .map_err(|e| WalletError::NetworkError(e.to_string()))?;
-
-        let (confirmed_balance, unconfirmed_balance) =
-            utxos.iter().fold((0u64, 0u64), |(cb, ub), utxo| {
-                if let Some(value) = utxo["value"].as_u64() {
-                    if utxo["status"]
-                        .get("confirmed")
-                        .and_then(|v| v.as_bool())
-                        .unwrap_or(false)
-                    {
-                        (cb + value, ub)
-                    } else {
-                        (cb, ub + value)
-                    }
-                } else {
-                    (cb, ub)
-                }
-            });
+        Ok(balance_of(address.to_string(), &utxos, unit))
+    }
+
+    /// Transactions touching `address` with their net effect on it.
+    pub async fn get_history(&self, address: &str) -> WalletResult<AddressHistoryResponse> {
+        let addr = parse_address(address, self.network)?;
+        let history = self.chain.get_history(&addr).await?;
+        let tip = self.chain.tip_height().await?;
+
+        let transactions = history
+            .into_iter()
+            .map(|tx| HistoryEntry {
+                txid: tx.txid.to_string(),
+                received: tx.received,
+                sent: tx.sent,
+                net: SignedAmount::from_sat(tx.received.to_sat() as i64 - tx.sent.to_sat() as i64),
+                fee: tx.fee,
+                confirmed: tx.height.is_some(),
+                height: tx.height,
+                confirmations: tx.height.map_or(0, |height| tip.saturating_sub(height) + 1),
+            })
+            .collect();
+
+        Ok(AddressHistoryResponse {
+            address: address.to_string(),
+            tip_height: tip,
+            transactions,
+        })
+    }
+
+    /// Unspent outputs of a single address with confirmations and charm flags.
+    pub async fn get_address_utxos(&self, address: &str) -> WalletResult<AddressUtxosResponse> {
+        let addr = parse_address(address, self.network)?;
+        let utxos = self.chain.get_utxos(&addr).await?;
+        let tip = self.chain.tip_height().await?;
 
-        Ok(BalanceResponse {
+        let mut charm_outputs = HashMap::new();
+        let utxos = self
+            .annotate(&addr, None, &utxos, tip, &mut charm_outputs)
+            .await?;
+
+        Ok(AddressUtxosResponse {
             address: address.to_string(),
-            balance: (confirmed_balance as f64) / 100_000_000.0,
-            unconfirmed_balance: (unconfirmed_balance as f64) / 100_000_000.0,
+            tip_height: tip,
+            utxos,
+        })
+    }
+
+    /// Balance summed over every used address of a wallet's descriptor chains.
+    pub async fn get_wallet_balance(
+        &self,
+        wallet_id: &str,
+        chains: &[DescriptorChain],
+        unit: Option<Denomination>,
+    ) -> WalletResult<WalletBalanceResponse> {
+        let scanned = self.scan(wallet_id, chains).await?;
+
+        let addresses: Vec<BalanceResponse> = scanned
+            .iter()
+            .map(|entry| balance_of(entry.address.to_string(), &entry.utxos, unit))
+            .collect();
+        let balance = addresses.iter().map(|a| a.balance).sum();
+        let unconfirmed_balance = addresses.iter().map(|a| a.unconfirmed_balance).sum();
+
+        Ok(WalletBalanceResponse {
+            wallet_id: wallet_id.to_string(),
+            balance,
+            unconfirmed_balance,
+            display: unit.map(|unit| BalanceDisplay::new(balance, unconfirmed_balance, unit)),
+            addresses,
         })
     }
 
-    /// Broadcasts a transaction to the Bitcoin network.
-    /// Takes a raw transaction hex string that has been signed by the frontend.
-    pub async fn broadcast_transaction(
+    /// UTXOs of a wallet's descriptor chains, optionally only those carrying charms.
+    pub async fn get_wallet_utxos(
         &self,
-        request: &BroadcastTxRequest,
-    ) -> WalletResult<BroadcastTxResponse> {
-        let rpc_client = RpcClient::new(
-            "http://localhost:48332",
-            Auth::UserPass("hello".into(), "world".into()),
-        )
-        .map_err(|e| WalletError::BitcoinError(format!("RPC client creation failed: {}", e)))?;
-
-        let tx_bytes = Vec::<u8>::from_hex(&request.tx_hex)
-            .map_err(|e| WalletError::BitcoinError(format!("Invalid hex: {}", e)))?;
-        let tx: Transaction = deserialize(&tx_bytes)
-            .map_err(|e| WalletError::BitcoinError(format!("Deserialization failed: {}", e)))?;
-
-        let txid = rpc_client
-            .send_raw_transaction(&tx)
-            .map_err(|e| WalletError::BitcoinError(format!("Broadcast failed: {}", e)))?;
-
-        Ok(BroadcastTxResponse {
-            txid: txid.to_string(),
+        wallet_id: &str,
+        chains: &[DescriptorChain],
+        charms_only: bool,
+    ) -> WalletResult<WalletUtxosResponse> {
+        let scanned = self.scan(wallet_id, chains).await?;
+        let tip = self.chain.tip_height().await?;
+
+        let mut charm_outputs = HashMap::new();
+        let mut utxos = Vec::new();
+        for entry in &scanned {
+            let annotated = self
+                .annotate(
+                    &entry.address,
+                    Some(entry.change),
+                    &entry.utxos,
+                    tip,
+                    &mut charm_outputs,
+                )
+                .await?;
+            utxos.extend(
+                annotated
+                    .into_iter()
+                    .filter(|utxo| utxo.has_charms || !charms_only),
+            );
+        }
+
+        Ok(WalletUtxosResponse {
+            wallet_id: wallet_id.to_string(),
+            tip_height: tip,
+            utxos,
+        })
+    }
+
+    /// Derives `count` addresses from index `from` on every receive or change
+    /// chain in `chains`, marking the ones with history.
+    ///
+    /// The next unused index starts from the node wallet's own index for the
+    /// chain and moves past any used address in the range.
+    pub async fn derive_addresses(
+        &self,
+        wallet_id: &str,
+        chains: &[DescriptorChain],
+        kind: AddressKind,
+        from: u32,
+        count: u32,
+    ) -> WalletResult<WalletAddressesResponse> {
+        if count == 0 || count > MAX_DERIVE_COUNT {
+            return Err(WalletError::InvalidAmount(format!(
+                "count must be between 1 and {}",
+                MAX_DERIVE_COUNT
+            )));
+        }
+        let change = kind == AddressKind::Change;
+
+        let mut responses = Vec::new();
+        for chain in chains.iter().filter(|chain| chain.change == change) {
+            let descriptor = chain.descriptor();
+            let mut next_unused = chain.next_index;
+
+            let indices = from..from.saturating_add(count);
+            let derived = indices
+                .clone()
+                .map(|index| chain.address(&self.secp, index, self.network))
+                .collect::<WalletResult<Vec<_>>>()?;
+            let looked_up = self.look_up(&derived).await?;
+
+            let mut addresses = Vec::new();
+            for ((index, address), (_, has_history)) in indices.zip(derived).zip(looked_up) {
+                if has_history {
+                    next_unused = next_unused.max(index + 1);
+                }
+                addresses.push(DerivedAddress {
+                    index,
+                    address: address.to_string(),
+                    has_history,
+                });
+            }
+
+            responses.push(ChainAddresses {
+                descriptor,
+                script_type: chain.kind,
+                next_unused_index: next_unused,
+                addresses,
+            });
+        }
+
+        Ok(WalletAddressesResponse {
+            wallet_id: wallet_id.to_string(),
+            kind,
+            chains: responses,
         })
     }
+
+    /// Unspent outputs of a wallet's descriptor chains, with key origins.
+    pub async fn owned_utxos(
+        &self,
+        wallet_id: &str,
+        chains: &[DescriptorChain],
+    ) -> WalletResult<Vec<OwnedUtxo>> {
+        let mut charm_outputs = HashMap::new();
+        let mut owned = Vec::new();
+        for entry in self.scan(wallet_id, chains).await? {
+            let chain = &chains[entry.chain];
+            let key_source = chain.key_source(&self.secp, entry.index)?;
+            for utxo in entry.utxos {
+                owned.push(OwnedUtxo {
+                    has_charms: self.has_charms(&utxo.outpoint, &mut charm_outputs).await?,
+                    txout: TxOut {
+                        value: utxo.value,
+                        script_pubkey: entry.address.script_pubkey(),
+                    },
+                    kind: Some(chain.kind),
+                    key_source: Some(key_source.clone()),
+                    utxo,
+                });
+            }
+        }
+        Ok(owned)
+    }
+
+    /// Unspent outputs of a single address. Key origins are unknown.
+    pub async fn owned_address_utxos(&self, address: &Address) -> WalletResult<Vec<OwnedUtxo>> {
+        let mut charm_outputs = HashMap::new();
+        let mut owned = Vec::new();
+        for utxo in self.chain.get_utxos(address).await? {
+            owned.push(OwnedUtxo {
+                has_charms: self.has_charms(&utxo.outpoint, &mut charm_outputs).await?,
+                txout: TxOut {
+                    value: utxo.value,
+                    script_pubkey: address.script_pubkey(),
+                },
+                kind: ScriptKind::of(address),
+                key_source: None,
+                utxo,
+            });
+        }
+        Ok(owned)
+    }
+
+    /// Walks each chain until the gap limit of consecutive unused addresses,
+    /// returning the used ones.
+    async fn scan(
+        &self,
+        wallet_id: &str,
+        chains: &[DescriptorChain],
+    ) -> WalletResult<Vec<ScannedAddress>> {
+        let gap_limit = self.gap_limit;
+
+        let mut used = Vec::new();
+        for (chain_index, chain) in chains.iter().enumerate() {
+            let mut gap = 0;
+            let mut next = 0;
+            while gap < gap_limit {
+                // Everything that could still end the scan goes in one batch.
+                let window = next..next + (gap_limit - gap);
+                let addresses = window
+                    .clone()
+                    .map(|index| chain.address(&self.secp, index, self.network))
+                    .collect::<WalletResult<Vec<_>>>()?;
+                let looked_up = self.look_up(&addresses).await?;
+
+                for ((index, address), (utxos, is_used)) in
+                    window.clone().zip(addresses).zip(looked_up)
+                {
+                    if is_used {
+                        gap = 0;
+                        used.push(ScannedAddress {
+                            address,
+                            change: chain.change,
+                            chain: chain_index,
+                            index,
+                            utxos,
+                        });
+                    } else {
+                        gap += 1;
+                    }
+                }
+                next = window.end;
+            }
+        }
+
+        tracing::info!(
+            "Scanned wallet {}: {} used addresses",
+            wallet_id,
+            used.len()
+        );
+        Ok(used)
+    }
+
+    /// UTXOs of `addresses`, fetched in one batch, and whether each address
+    /// has ever been used.
+    async fn look_up(&self, addresses: &[Address]) -> WalletResult<Vec<(Vec<Utxo>, bool)>> {
+        let utxos = self.chain.get_utxos_batch(addresses).await?;
+        let mut looked_up = Vec::with_capacity(addresses.len());
+        for (address, utxos) in addresses.iter().zip(utxos) {
+            let used = !utxos.is_empty() || self.chain.has_history(address).await?;
+            looked_up.push((utxos, used));
+        }
+        Ok(looked_up)
+    }
+
+    /// Whether `outpoint` carries charms. Charms are recorded in the spell of
+    /// the transaction that created an output, so each funding transaction is
+    /// fetched once and its per-output flags kept in `charm_outputs`.
+    async fn has_charms(
+        &self,
+        outpoint: &OutPoint,
+        charm_outputs: &mut HashMap<Txid, Vec<bool>>,
+    ) -> WalletResult<bool> {
+        if let Entry::Vacant(entry) = charm_outputs.entry(outpoint.txid) {
+            let tx = self.chain.get_transaction(&outpoint.txid).await?;
+            entry.insert(charm_flags(&tx));
+        }
+        Ok(charm_outputs[&outpoint.txid]
+            .get(outpoint.vout as usize)
+            .copied()
+            .unwrap_or(false))
+    }
+
+    /// Describes `utxos` held by `address`.
+    async fn annotate(
+        &self,
+        address: &Address,
+        change: Option<bool>,
+        utxos: &[Utxo],
+        tip: u32,
+        charm_outputs: &mut HashMap<Txid, Vec<bool>>,
+    ) -> WalletResult<Vec<UtxoResponse>> {
+        let script_type = address
+            .address_type()
+            .map_or_else(|| "unknown".to_string(), |kind| kind.to_string());
+
+        let mut responses = Vec::new();
+        for utxo in utxos {
+            let has_charms = self.has_charms(&utxo.outpoint, charm_outputs).await?;
+            responses.push(UtxoResponse {
+                txid: utxo.outpoint.txid.to_string(),
+                vout: utxo.outpoint.vout,
+                address: address.to_string(),
+                script_type: script_type.clone(),
+                change,
+                value: utxo.value,
+                height: utxo.height,
+                confirmations: utxo
+                    .height
+                    .map_or(0, |height| tip.saturating_sub(height) + 1),
+                has_charms,
+            });
+        }
+        Ok(responses)
+    }
+}
+
+/// Most addresses derived per chain in one request.
+const MAX_DERIVE_COUNT: u32 = 100;
+
+fn balance_of(address: String, utxos: &[Utxo], unit: Option<Denomination>) -> BalanceResponse {
+    let (balance, unconfirmed_balance) =
+        utxos
+            .iter()
+            .fold((Amount::ZERO, Amount::ZERO), |(cb, ub), utxo| {
+                if utxo.is_confirmed() {
+                    (cb + utxo.value, ub)
+                } else {
+                    (cb, ub + utxo.value)
+                }
+            });
+
+    BalanceResponse {
+        address,
+        balance,
+        unconfirmed_balance,
+        display: unit.map(|unit| BalanceDisplay::new(balance, unconfirmed_balance, unit)),
+    }
+}
+
+/// For each output of `tx`, whether its spell assigns charms to it.
+fn charm_flags(tx: &bitcoin::Transaction) -> Vec<bool> {
+    let outs = charms::tx::norm_spell(tx)
+        .map(|spell| spell.tx.outs)
+        .unwrap_or_default();
+    (0..tx.output.len())
+        .map(|vout| outs.get(vout).is_some_and(|charms| !charms.is_empty()))
+        .collect()
 }
@@ -0,0 +1,124 @@
+// api/src/services/fees.rs
+use crate::chain::ChainBackend;
+use crate::config::FeeConfig;
+use crate::error::{WalletError, WalletResult};
+use crate::models::FeeEstimateResponse;
+use bitcoin::FeeRate;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Fee estimates from the chain backend, cached and clamped to the
+/// configured floor and ceiling.
+pub struct FeeService {
+    chain: Arc<dyn ChainBackend>,
+    config: FeeConfig,
+    cache: Mutex<HashMap<u16, (Instant, FeeRate)>>,
+}
+
+impl FeeService {
+    pub fn new(chain: Arc<dyn ChainBackend>, config: FeeConfig) -> Self {
+        Self {
+            chain,
+            config,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Lowest fee rate the service will hand out.
+    pub fn min_fee_rate(&self) -> FeeRate {
+        FeeRate::from_sat_per_kwu(self.config.min_sat_vb * 250)
+    }
+
+    /// Highest fee rate the service will hand out.
+    pub fn max_fee_rate(&self) -> FeeRate {
+        FeeRate::from_sat_per_kwu(self.config.max_sat_vb * 250)
+    }
+
+    /// Fee rate expected to confirm within `conf_target` blocks.
+    ///
+    /// Falls back to the floor when the backend has no estimate yet, which is
+    /// common on regtest and freshly synced nodes.
+    pub async fn fee_rate_for_target(&self, conf_target: u16) -> WalletResult<FeeRate> {
+        let ttl = Duration::from_secs(self.config.cache_secs);
+        if let Some((fetched_at, rate)) = self.cache.lock().unwrap().get(&conf_target) {
+            if fetched_at.elapsed() < ttl {
+                return Ok(*rate);
+            }
+        }
+
+        let rate = match self.chain.estimate_fee(conf_target).await? {
+            Some(rate) => rate.clamp(self.min_fee_rate(), self.max_fee_rate()),
+            None => {
+                tracing::warn!(
+                    "No fee estimate for {} blocks from {}, using the floor",
+                    conf_target,
+                    self.chain.name()
+                );
+                self.min_fee_rate()
+            }
+        };
+
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(conf_target, (Instant::now(), rate));
+        Ok(rate)
+    }
+
+    /// Fee rate for a caller-supplied choice.
+    ///
+    /// An explicit rate must lie between the floor (the relay minimum) and the
+    /// sanity ceiling; otherwise the estimate for `conf_target`, or the
+    /// configured medium target, is used.
+    pub async fn requested_fee_rate(
+        &self,
+        fee_rate_sat_vb: Option<u64>,
+        conf_target: Option<u16>,
+    ) -> WalletResult<FeeRate> {
+        if let Some(sat_vb) = fee_rate_sat_vb {
+            if sat_vb < self.config.min_sat_vb {
+                return Err(WalletError::InvalidAmount(format!(
+                    "Fee rate {} sat/vB is below the minimum relay fee of {} sat/vB",
+                    sat_vb, self.config.min_sat_vb
+                )));
+            }
+            if sat_vb > self.config.max_sat_vb {
+                return Err(WalletError::InvalidAmount(format!(
+                    "Fee rate {} sat/vB is above the maximum of {} sat/vB",
+                    sat_vb, self.config.max_sat_vb
+                )));
+            }
+            return Ok(FeeRate::from_sat_per_kwu(sat_vb * 250));
+        }
+
+        let target = conf_target.unwrap_or(self.config.medium_target);
+        if target == 0 || target > FeeConfig::MAX_TARGET {
+            return Err(WalletError::InvalidAmount(format!(
+                "conf_target must be between 1 and {} blocks",
+                FeeConfig::MAX_TARGET
+            )));
+        }
+        self.fee_rate_for_target(target).await
+    }
+
+    /// Fast, medium and slow estimates in sat/vB.
+    pub async fn estimates(&self) -> WalletResult<FeeEstimateResponse> {
+        Ok(FeeEstimateResponse {
+            fast: self
+                .fee_rate_for_target(self.config.fast_target)
+                .await?
+                .to_sat_per_vb_ceil(),
+            medium: self
+                .fee_rate_for_target(self.config.medium_target)
+                .await?
+                .to_sat_per_vb_ceil(),
+            slow: self
+                .fee_rate_for_target(self.config.slow_target)
+                .await?
+                .to_sat_per_vb_ceil(),
+        })
+    }
+}
@@ -0,0 +1,421 @@
+// api/src/services/hd.rs
+use crate::error::{WalletError, WalletResult};
+use crate::network::network_name;
+use bitcoin::bip32::{ChildNumber, DerivationPath, Fingerprint, KeySource, Xpub};
+use bitcoin::secp256k1::{PublicKey, Secp256k1, Verification};
+use bitcoin::{Address, Network, NetworkKind, Script};
+use bitcoincore_rpc::json::AddressType;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Script types the wallet derives accounts for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScriptKind {
+    /// BIP84 native segwit v0.
+    #[default]
+    P2wpkh,
+    /// BIP86 single-key taproot, key path only.
+    P2tr,
+}
+
+impl ScriptKind {
+    pub const ALL: [ScriptKind; 2] = [ScriptKind::P2wpkh, ScriptKind::P2tr];
+
+    /// Bitcoin Core's name for addresses of this type.
+    pub fn address_type(self) -> AddressType {
+        match self {
+            ScriptKind::P2wpkh => AddressType::Bech32,
+            ScriptKind::P2tr => AddressType::Bech32m,
+        }
+    }
+
+    pub fn descriptor_fn(self) -> &'static str {
+        match self {
+            ScriptKind::P2wpkh => "wpkh",
+            ScriptKind::P2tr => "tr",
+        }
+    }
+
+    /// Script type of `address`, `None` for types the wallet does not derive.
+    pub fn of(address: &Address) -> Option<Self> {
+        match address.address_type()? {
+            bitcoin::AddressType::P2wpkh => Some(ScriptKind::P2wpkh),
+            bitcoin::AddressType::P2tr => Some(ScriptKind::P2tr),
+            _ => None,
+        }
+    }
+
+    /// Script type of an output's `script_pubkey`.
+    pub fn of_script(script_pubkey: &Script) -> Option<Self> {
+        if script_pubkey.is_p2wpkh() {
+            Some(ScriptKind::P2wpkh)
+        } else if script_pubkey.is_p2tr() {
+            Some(ScriptKind::P2tr)
+        } else {
+            None
+        }
+    }
+}
+
+/// One ranged single-key descriptor, e.g. `wpkh([fp/84'/1'/0']tpub.../0/*)`.
+///
+/// Only the `wpkh` and `tr` key-path forms this wallet produces itself are
+/// understood; anything else is rejected as an invalid key.
+#[derive(Debug, Clone)]
+pub struct DescriptorChain {
+    pub kind: ScriptKind,
+    origin: Option<(Fingerprint, DerivationPath)>,
+    xpub: Xpub,
+    /// Derivation steps between the xpub and the wildcard.
+    path: Vec<ChildNumber>,
+    /// Whether this is an internal (change) chain, i.e. its path ends in `/1`.
+    pub change: bool,
+    /// Next index the node wallet hands out on this chain, 0 for chains not
+    /// read from the node.
+    pub next_index: u32,
+}
+
+impl DescriptorChain {
+    /// Parses a descriptor, expanding a `/<0;1>/*` multipath into its
+    /// receive and change chains. A trailing checksum is verified if present.
+    pub fn parse(descriptor: &str, network: Network) -> WalletResult<Vec<Self>> {
+        let descriptor = descriptor.trim();
+        let body = match descriptor.split_once('#') {
+            Some((body, checksum)) => {
+                if with_checksum(body) != descriptor {
+                    return Err(WalletError::InvalidKey(format!(
+                        "Descriptor checksum mismatch: {}",
+                        checksum
+                    )));
+                }
+                body
+            }
+            None => descriptor,
+        };
+
+        let (kind, inner) = ScriptKind::ALL
+            .into_iter()
+            .find_map(|kind| {
+                body.strip_prefix(kind.descriptor_fn())
+                    .and_then(|rest| rest.strip_prefix('('))
+                    .and_then(|rest| rest.strip_suffix(')'))
+                    .map(|inner| (kind, inner))
+            })
+            .ok_or_else(|| {
+                WalletError::InvalidKey(
+                    "Only wpkh(...) and tr(...) descriptors are supported".to_string(),
+                )
+            })?;
+
+        let (origin, key_path) = match inner.strip_prefix('[') {
+            Some(rest) => {
+                let (origin, key_path) = rest.split_once(']').ok_or_else(|| {
+                    WalletError::InvalidKey("Unterminated key origin".to_string())
+                })?;
+                (Some(parse_origin(origin)?), key_path)
+            }
+            None => (None, inner),
+        };
+
+        let mut steps = key_path.split('/');
+        let xpub = parse_xpub(steps.next().unwrap_or_default(), network)?;
+        let steps: Vec<&str> = steps.collect();
+        let Some((&"*", prefix)) = steps.split_last() else {
+            return Err(WalletError::InvalidKey(
+                "Descriptor must be ranged, ending in /*".to_string(),
+            ));
+        };
+
+        let mut paths = vec![Vec::new()];
+        for step in prefix {
+            if let Some(branches) = step.strip_prefix('<').and_then(|s| s.strip_suffix('>')) {
+                let branches = branches
+                    .split(';')
+                    .map(parse_normal)
+                    .collect::<WalletResult<Vec<_>>>()?;
+                paths = branches
+                    .iter()
+                    .flat_map(|branch| {
+                        paths.iter().map(move |path| {
+                            let mut path = path.clone();
+                            path.push(*branch);
+                            path
+                        })
+                    })
+                    .collect();
+            } else {
+                let child = parse_normal(step)?;
+                paths.iter_mut().for_each(|path| path.push(child));
+            }
+        }
+
+        Ok(paths
+            .into_iter()
+            .map(|path| Self {
+                kind,
+                origin: origin.clone(),
+                xpub,
+                change: path.last() == Some(&ChildNumber::Normal { index: 1 }),
+                path,
+                next_index: 0,
+            })
+            .collect())
+    }
+
+    /// Receive (`/0/*`) and change (`/1/*`) chains of an account xpub.
+    ///
+    /// `origin` is the xpub's `fingerprint/path` below the master key, e.g.
+    /// `d34db33f/84'/1'/0'`, so descriptors and PSBTs carry the full path.
+    pub fn from_xpub(
+        xpub: &str,
+        kind: ScriptKind,
+        origin: Option<&str>,
+        network: Network,
+    ) -> WalletResult<Vec<Self>> {
+        let xpub = parse_xpub(xpub, network)?;
+        let origin = origin.map(parse_origin).transpose()?;
+        if let Some((_, path)) = &origin {
+            if path.len() != usize::from(xpub.depth) {
+                return Err(WalletError::InvalidKey(format!(
+                    "Key origin path has {} steps but the xpub is at depth {}",
+                    path.len(),
+                    xpub.depth
+                )));
+            }
+        }
+        Ok([false, true]
+            .into_iter()
+            .map(|change| Self {
+                kind,
+                origin: origin.clone(),
+                xpub,
+                path: vec![ChildNumber::Normal {
+                    index: u32::from(change),
+                }],
+                change,
+                next_index: 0,
+            })
+            .collect())
+    }
+
+    /// Public descriptor with checksum.
+    pub fn descriptor(&self) -> String {
+        let origin = match &self.origin {
+            Some((fingerprint, path)) if path.is_empty() => format!("[{}]", fingerprint),
+            Some((fingerprint, path)) => format!("[{}/{}]", fingerprint, path),
+            None => String::new(),
+        };
+        let path: String = self
+            .path
+            .iter()
+            .map(|child| format!("/{}", child))
+            .collect();
+        with_checksum(&format!(
+            "{}({}{}{}/*)",
+            self.kind.descriptor_fn(),
+            origin,
+            self.xpub,
+            path
+        ))
+    }
+
+    /// Public key at `index` with its origin, as PSBT signers expect it. An
+    /// xpub without a recorded origin is treated as the root of the path.
+    pub fn key_source<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        index: u32,
+    ) -> WalletResult<(PublicKey, KeySource)> {
+        let mut path = self.path.clone();
+        path.push(normal(index)?);
+        let child = self
+            .xpub
+            .derive_pub(secp, &path)
+            .map_err(|e| WalletError::InvalidKey(e.to_string()))?;
+
+        let (fingerprint, origin_path) = match &self.origin {
+            Some((fingerprint, origin_path)) => (*fingerprint, origin_path.clone()),
+            None => (self.xpub.fingerprint(), DerivationPath::master()),
+        };
+        Ok((child.public_key, (fingerprint, origin_path.extend(&path))))
+    }
+
+    /// Address at `index` of this chain.
+    pub fn address<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        index: u32,
+        network: Network,
+    ) -> WalletResult<Address> {
+        let mut path = self.path.clone();
+        path.push(normal(index)?);
+        derive_address(secp, self.kind, &self.xpub, &path, network)
+    }
+}
+
+fn derive_address<C: Verification>(
+    secp: &Secp256k1<C>,
+    kind: ScriptKind,
+    xpub: &Xpub,
+    path: &[ChildNumber],
+    network: Network,
+) -> WalletResult<Address> {
+    let child = xpub
+        .derive_pub(secp, &path)
+        .map_err(|e| WalletError::InvalidKey(e.to_string()))?;
+
+    Ok(match kind {
+        ScriptKind::P2wpkh => Address::p2wpkh(&child.to_pub(), network),
+        ScriptKind::P2tr => Address::p2tr(secp, child.to_x_only_pub(), None, network),
+    })
+}
+
+/// Parses an extended public key and checks it was encoded for `network`.
+fn parse_xpub(key: &str, network: Network) -> WalletResult<Xpub> {
+    let xpub = Xpub::from_str(key).map_err(|_| {
+        WalletError::InvalidKey("Expected an extended public key (xpub/tpub)".to_string())
+    })?;
+    if xpub.network != NetworkKind::from(network) {
+        return Err(WalletError::InvalidKey(format!(
+            "Extended key is not for {}",
+            network_name(network)
+        )));
+    }
+    Ok(xpub)
+}
+
+fn parse_origin(origin: &str) -> WalletResult<(Fingerprint, DerivationPath)> {
+    let (fingerprint, path) = origin.split_once('/').unwrap_or((origin, ""));
+    let fingerprint = Fingerprint::from_str(fingerprint)
+        .map_err(|e| WalletError::InvalidKey(format!("Invalid key origin fingerprint: {}", e)))?;
+    let path = DerivationPath::from_str(path)
+        .map_err(|e| WalletError::InvalidKey(format!("Invalid key origin path: {}", e)))?;
+    Ok((fingerprint, path))
+}
+
+/// Parses one unhardened derivation step; public derivation cannot go through hardened ones.
+fn parse_normal(step: &str) -> WalletResult<ChildNumber> {
+    match ChildNumber::from_str(step) {
+        Ok(child @ ChildNumber::Normal { .. }) => Ok(child),
+        Ok(_) => Err(WalletError::InvalidKey(format!(
+            "Hardened step {} after an xpub cannot be derived",
+            step
+        ))),
+        Err(e) => Err(WalletError::InvalidKey(format!(
+            "Invalid derivation step {}: {}",
+            step, e
+        ))),
+    }
+}
+
+fn normal(index: u32) -> WalletResult<ChildNumber> {
+    ChildNumber::from_normal_idx(index).map_err(|e| WalletError::InvalidKey(e.to_string()))
+}
+
+/// Appends the BIP380 checksum to `descriptor`.
+pub fn with_checksum(descriptor: &str) -> String {
+    const INPUT_CHARSET: &str =
+        "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+    const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+    fn polymod(c: u64, val: u64) -> u64 {
+        let c0 = c >> 35;
+        let mut c = ((c & 0x7ffffffff) << 5) ^ val;
+        for (bit, generator) in [
+            0xf5dee51989,
+            0xa9fdca3312,
+            0x1bab10e32d,
+            0x3706b1677a,
+            0x644d626ffd,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            if c0 & (1 << bit) != 0 {
+                c ^= generator;
+            }
+        }
+        c
+    }
+
+    let mut c = 1;
+    let mut class = 0;
+    let mut class_count = 0;
+    for ch in descriptor.chars() {
+        // Descriptors built here only use characters from the input charset.
+        let pos = INPUT_CHARSET.find(ch).unwrap_or_default() as u64;
+        c = polymod(c, pos & 31);
+        class = class * 3 + (pos >> 5);
+        class_count += 1;
+        if class_count == 3 {
+            c = polymod(c, class);
+            class = 0;
+            class_count = 0;
+        }
+    }
+    if class_count > 0 {
+        c = polymod(c, class);
+    }
+    for _ in 0..8 {
+        c = polymod(c, 0);
+    }
+    c ^= 1;
+
+    let checksum: String = (0..8)
+        .map(|j| CHECKSUM_CHARSET[((c >> (5 * (7 - j))) & 31) as usize] as char)
+        .collect();
+    format!("{}#{}", descriptor, checksum)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::bip32::Xpriv;
+
+    fn account(path: &str) -> (Xpub, String) {
+        let secp = Secp256k1::new();
+        let master = Xpriv::new_master(Network::Testnet, &[7; 32]).unwrap();
+        let path = DerivationPath::from_str(path).unwrap();
+        let xpriv = master.derive_priv(&secp, &path).unwrap();
+        let origin = match path.is_empty() {
+            true => master.fingerprint(&secp).to_string(),
+            false => format!("{}/{}", master.fingerprint(&secp), path),
+        };
+        (Xpub::from_priv(&secp, &xpriv), origin)
+    }
+
+    fn round_trip(path: &str, kind: ScriptKind) {
+        let (xpub, origin) = account(path);
+        let chains =
+            DescriptorChain::from_xpub(&xpub.to_string(), kind, Some(&origin), Network::Testnet)
+                .unwrap();
+
+        for chain in chains {
+            let descriptor = chain.descriptor();
+            assert!(
+                descriptor.contains(&format!("[{}]", origin)),
+                "{}",
+                descriptor
+            );
+
+            let parsed = DescriptorChain::parse(&descriptor, Network::Testnet).unwrap();
+            assert_eq!(parsed.len(), 1);
+            assert_eq!(parsed[0].descriptor(), descriptor);
+            assert_eq!(parsed[0].change, chain.change);
+            assert_eq!(parsed[0].origin, chain.origin);
+        }
+    }
+
+    #[test]
+    fn descriptor_round_trips_with_master_origin() {
+        round_trip("m", ScriptKind::P2wpkh);
+        round_trip("m", ScriptKind::P2tr);
+    }
+
+    #[test]
+    fn descriptor_round_trips_with_account_origin() {
+        round_trip("84'/1'/0'", ScriptKind::P2wpkh);
+        round_trip("86'/1'/0'", ScriptKind::P2tr);
+    }
+}
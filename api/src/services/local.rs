@@ -1,30 +1,47 @@
+use crate::chain::{blocking_rpc, rpc_client, ChainBackend};
+use crate::config::RpcConfig;
 use crate::error::{WalletError, WalletResult};
 use crate::models::*;
-use bitcoin::key::CompressedPublicKey;
-use bitcoin::secp256k1::{Secp256k1, SecretKey};
-use bitcoin::{consensus::encode::serialize_hex, Network, OutPoint, PrivateKey, Transaction, Txid};
-use bitcoincore_rpc::{Auth, Client as RpcClient, RpcApi};
-use rand::thread_rng;
-use std::{env, str::FromStr};
+use crate::services::hd::{DescriptorChain, ScriptKind};
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::{Address, Network, OutPoint, TxOut, Txid};
+use bitcoincore_rpc::json::{ImportDescriptors, ScanningDetails, Timestamp};
+use bitcoincore_rpc::{jsonrpc, Client as RpcClient, RpcApi};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    future::Future,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+use uuid::{Builder, Uuid};
 
+/// Bitcoin Core's error code for a wallet that does not exist or is not loaded.
+const RPC_WALLET_NOT_FOUND: i32 = -18;
+/// Bitcoin Core's error code for loading a wallet that is already loaded.
+const RPC_WALLET_ALREADY_LOADED: i32 = -35;
+
+/// Change addresses the chain backend already knows as used that
+/// [`LocalWalletService::next_change_address`] skips before giving up.
+const MAX_USED_CHANGE_SKIPS: usize = 20;
+
+/// Node wallets holding the public descriptors of client-side keys.
+///
+/// Every wallet is created with private keys disabled: the node tracks
+/// addresses and history, and signing happens wherever the keys live.
 pub struct LocalWalletService {
     network: Network,
+    rpc: RpcConfig,
+    /// Node-level client, shared with the rest of the app.
+    node: Arc<RpcClient>,
+    chain: Arc<dyn ChainBackend>,
+    /// Clients scoped to `/wallet/<id>`, built on first use and kept until
+    /// the node reports the wallet missing.
+    wallets: Mutex<HashMap<String, Arc<RpcClient>>>,
     secp: Secp256k1<bitcoin::secp256k1::All>,
 }
 
-fn get_rpc_client() -> WalletResult<RpcClient> {
-    let host = env::var("BITCOIN_RPC_HOST").unwrap_or_else(|_| "localhost".to_string());
-    let port = env::var("BITCOIN_RPC_PORT").unwrap_or_else(|_| "18332".to_string());
-    let user = env::var("BITCOIN_RPC_USER").unwrap_or_else(|_| "hello".to_string());
-    let password = env::var("BITCOIN_RPC_PASSWORD").unwrap_or_else(|_| "world".to_string());
-
-    RpcClient::new(
-        &format!("http://{}:{}", host, port),
-        Auth::UserPass(user, password),
-    )
-    .map_err(|e| WalletError::BitcoinError(e.to_string()))
-}
-
 pub fn parse_outpoint(s: &str) -> WalletResult<OutPoint> {
     let parts: Vec<&str> = s.split(':').collect();
     if parts.len() != 2 {
@@ -42,84 +59,670 @@ pub fn parse_outpoint(s: &str) -> WalletResult<OutPoint> {
     Ok(OutPoint::new(txid, vout))
 }
 
-pub fn get_funding_utxo_value(utxo: OutPoint) -> WalletResult<u64> {
-    let rpc_client = get_rpc_client()?;
-    let tx_out = rpc_client
-        .get_tx_out(&utxo.txid, utxo.vout, Some(false))
-        .map_err(|e| WalletError::BitcoinError(format!("Failed to get tx_out: {}", e)))?
-        .ok_or_else(|| WalletError::BitcoinError("UTXO not found".to_string()))?;
-
-    Ok(tx_out.value.to_sat())
+pub async fn get_funding_utxo(chain: &dyn ChainBackend, utxo: OutPoint) -> WalletResult<TxOut> {
+    chain
+        .get_tx_out(&utxo)
+        .await?
+        .ok_or_else(|| WalletError::BitcoinError("UTXO not found".to_string()))
 }
 
-pub fn get_change_address() -> WalletResult<String> {
-    let rpc_client = get_rpc_client()?;
-    let address = rpc_client
-        .get_new_address(None, None)
-        .map_err(|e| WalletError::BitcoinError(format!("Failed to get change address: {}", e)))?;
-
-    Ok(address.assume_checked().to_string())
-}
+impl LocalWalletService {
+    pub fn new(
+        rpc: RpcConfig,
+        node: Arc<RpcClient>,
+        chain: Arc<dyn ChainBackend>,
+        network: Network,
+    ) -> Self {
+        Self {
+            network,
+            rpc,
+            node,
+            chain,
+            wallets: Mutex::new(HashMap::new()),
+            secp: Secp256k1::new(),
+        }
+    }
 
-// DELETE Replaced by charms one
-pub fn get_prev_txs(tx: &Transaction) -> WalletResult<Vec<String>> {
-    let rpc_client = get_rpc_client()?;
-    let mut prev_txs = Vec::new();
+    /// Creates a node wallet for freshly generated account xpubs.
+    ///
+    /// The receive and change descriptors of each account are imported
+    /// watch-only. New keys have no history, so the node does not rescan.
+    pub async fn create_wallet(
+        &self,
+        request: &CreateWalletRequest,
+    ) -> WalletResult<CreateWalletResponse> {
+        if request.accounts.is_empty() {
+            return Err(WalletError::InvalidKey(
+                "At least one account xpub is required".to_string(),
+            ));
+        }
+        for kind in ScriptKind::ALL {
+            let count = request
+                .accounts
+                .iter()
+                .filter(|account| account.script_type == kind)
+                .count();
+            if count > 1 {
+                return Err(WalletError::InvalidKey(format!(
+                    "Only one {:?} account can be registered",
+                    kind
+                )));
+            }
+        }
 
-    for input in &tx.input {
-        let raw_tx = rpc_client
-            .get_raw_transaction(&input.previous_output.txid, None)
-            .map_err(|e| {
-                WalletError::BitcoinError(format!("Failed to get raw transaction: {}", e))
+        let mut chains = Vec::new();
+        let mut accounts = Vec::new();
+        for account in &request.accounts {
+            let account_chains = DescriptorChain::from_xpub(
+                &account.xpub,
+                account.script_type,
+                account.origin.as_deref(),
+                self.network,
+            )?;
+            if let [receive, change] = account_chains.as_slice() {
+                accounts.push(AccountResponse {
+                    script_type: account.script_type,
+                    receive_descriptor: receive.descriptor(),
+                    change_descriptor: change.descriptor(),
+                    address: receive.address(&self.secp, 0, self.network)?.to_string(),
+                });
+            }
+            chains.extend(account_chains);
+        }
+        let address = accounts
+            .iter()
+            .find(|account| account.script_type == request.address_type)
+            .map(|account| account.address.clone())
+            .ok_or_else(|| {
+                WalletError::InvalidKey(format!(
+                    "No {:?} account for the requested address type",
+                    request.address_type
+                ))
             })?;
-        prev_txs.push(serialize_hex(&raw_tx));
+
+        let wallet_id = Uuid::new_v4().to_string();
+        self.create_node_wallet(&wallet_id, &chains, Timestamp::Now)
+            .await?;
+
+        Ok(CreateWalletResponse {
+            wallet_id,
+            address,
+            address_type: request.address_type,
+            accounts,
+        })
     }
 
-    Ok(prev_txs)
-}
+    /// Imports an existing wallet from a public descriptor or account xpub.
+    ///
+    /// Imported keys may have history, so the node rescans the chain from
+    /// the wallet's birthday height, or genesis if none is given. The rescan
+    /// runs in the background; the wallet id is returned straight away and
+    /// [`LocalWalletService::rescan_status`] reports progress.
+    pub async fn import_wallet(
+        &self,
+        request: &ImportWalletRequest,
+    ) -> WalletResult<ImportWalletResponse> {
+        let chains = match (&request.descriptor, &request.xpub) {
+            (Some(descriptor), None) => {
+                let chains = DescriptorChain::parse(descriptor, self.network)?;
+                if let Some(kind) = request.script_type {
+                    if chains.iter().any(|chain| chain.kind != kind) {
+                        return Err(WalletError::InvalidKey(format!(
+                            "Descriptor does not produce {:?} addresses",
+                            kind
+                        )));
+                    }
+                }
+                chains
+            }
+            (None, Some(xpub)) => DescriptorChain::from_xpub(
+                xpub,
+                request.script_type.unwrap_or_default(),
+                request.origin.as_deref(),
+                self.network,
+            )?,
+            _ => {
+                return Err(WalletError::InvalidKey(
+                    "Provide exactly one of descriptor or xpub".to_string(),
+                ))
+            }
+        };
 
-impl LocalWalletService {
-    pub fn new() -> WalletResult<Self> {
-        Ok(Self {
-            network: Network::Testnet,
-            secp: Secp256k1::new(),
+        let rescan_from_height = request.birthday_height.unwrap_or(0);
+        let tip = blocking_rpc(&self.node, |client| {
+            client
+                .get_block_count()
+                .map_err(|e| WalletError::BitcoinError(format!("Failed to get block count: {}", e)))
+        })
+        .await?;
+        if u64::from(rescan_from_height) > tip {
+            return Err(WalletError::InvalidRequest(format!(
+                "birthday_height {} is above the chain tip at {}",
+                rescan_from_height, tip
+            )));
+        }
+
+        // Importing with the current time keeps importdescriptors from
+        // rescanning while the request waits; the rescan is started below.
+        let wallet_id = Uuid::new_v4().to_string();
+        self.create_node_wallet(&wallet_id, &chains, Timestamp::Now)
+            .await?;
+        self.start_rescan(&wallet_id, rescan_from_height)?;
+
+        Ok(ImportWalletResponse {
+            wallet_id,
+            descriptors: chains.iter().map(DescriptorChain::descriptor).collect(),
+            addresses: chains
+                .iter()
+                .map(|chain| {
+                    Ok(ImportedAddress {
+                        address: chain.address(&self.secp, 0, self.network)?.to_string(),
+                        script_type: chain.kind,
+                        change: chain.change,
+                    })
+                })
+                .collect::<WalletResult<Vec<_>>>()?,
+            rescan_from_height,
         })
     }
 
-    pub async fn create_wallet(&self, password: &str) -> WalletResult<KeyPair> {
-        let wallet_name = format!("wallet_{}", password);
+    /// Whether the node is still rescanning for the wallet `wallet_id`.
+    pub async fn rescan_status(&self, wallet_id: &str) -> WalletResult<RescanStatusResponse> {
+        let wallet_id = parse_wallet_id(wallet_id)?;
 
-        let host = env::var("BITCOIN_RPC_HOST").unwrap_or_else(|_| "localhost".to_string());
-        let port = env::var("BITCOIN_RPC_PORT").unwrap_or_else(|_| "18332".to_string());
-        let user = env::var("BITCOIN_RPC_USER").unwrap_or_else(|_| "hello".to_string());
-        let password = env::var("BITCOIN_RPC_PASSWORD").unwrap_or_else(|_| "world".to_string());
+        let id = wallet_id.clone();
+        let info = self
+            .wallet_rpc(&wallet_id, move |client| {
+                client
+                    .get_wallet_info()
+                    .map_err(|e| wallet_rpc_error(e, &id))
+            })
+            .await?;
 
-        let rpc_client = RpcClient::new(
-            &format!("http://{}:{}/wallet/{}", host, port, wallet_name),
-            Auth::UserPass(user, password),
-        )
-        .map_err(|e| WalletError::BitcoinError(e.to_string()))?;
+        let progress = match info.scanning {
+            Some(ScanningDetails::Scanning { progress, .. }) => Some(progress),
+            _ => None,
+        };
+        Ok(RescanStatusResponse {
+            wallet_id,
+            scanning: progress.is_some(),
+            progress,
+        })
+    }
+
+    /// Starts rescanning the chain from `height` for the wallet `wallet_id`
+    /// without waiting for it. The node keeps going if the RPC call times
+    /// out; the outcome is only logged.
+    fn start_rescan(&self, wallet_id: &str, height: u32) -> WalletResult<()> {
+        let client = self.wallet_client(wallet_id)?;
+        let wallet_id = wallet_id.to_string();
+        tokio::task::spawn_blocking(move || {
+            match client.rescan_blockchain(Some(height as usize), None) {
+                Ok((start, stop)) => tracing::info!(
+                    "Rescanned wallet {} from {} to {:?}",
+                    wallet_id,
+                    start,
+                    stop
+                ),
+                Err(e) => tracing::warn!("Rescan of wallet {} ended: {}", wallet_id, e),
+            }
+        });
+        Ok(())
+    }
+
+    /// Registers a watch-only wallet from a descriptor or an account xpub.
+    ///
+    /// The chains are kept in a node wallet like those of created wallets,
+    /// so registrations survive restarts. The wallet id is derived from the
+    /// descriptors: registering the same chains again returns the existing
+    /// wallet rather than adding another one. Addresses are found by scanning
+    /// through the chain backend, so the node does not rescan.
+    pub async fn watch_wallet(
+        &self,
+        request: &WatchWalletRequest,
+        gap_limit: u32,
+    ) -> WalletResult<WatchWalletResponse> {
+        let chains = match (&request.descriptor, &request.xpub) {
+            (Some(descriptor), None) => DescriptorChain::parse(descriptor, self.network)?,
+            (None, Some(xpub)) => {
+                DescriptorChain::from_xpub(xpub, request.script_type, None, self.network)?
+            }
+            _ => {
+                return Err(WalletError::InvalidKey(
+                    "Provide exactly one of descriptor or xpub".to_string(),
+                ))
+            }
+        };
+        let descriptors: Vec<String> = chains.iter().map(DescriptorChain::descriptor).collect();
+
+        let wallet_id = watch_wallet_id(&descriptors);
+        match self.descriptor_chains(&wallet_id).await {
+            Ok(_) => tracing::info!("Wallet {} is already registered", wallet_id),
+            Err(WalletError::WalletNotFound(_)) => {
+                self.create_node_wallet(&wallet_id, &chains, Timestamp::Now)
+                    .await?
+            }
+            Err(e) => return Err(e),
+        }
+
+        Ok(WatchWalletResponse {
+            wallet_id,
+            descriptors,
+            gap_limit,
+        })
+    }
+
+    /// Creates a blank watch-only node wallet named `wallet_id` and imports
+    /// `chains` into it as active descriptors. The node loads it again on
+    /// startup.
+    async fn create_node_wallet(
+        &self,
+        wallet_id: &str,
+        chains: &[DescriptorChain],
+        timestamp: Timestamp,
+    ) -> WalletResult<()> {
+        let name = wallet_id.to_string();
+        blocking_rpc(&self.node, move |client| {
+            // name, disable_private_keys, blank, passphrase, avoid_reuse,
+            // descriptors, load_on_startup
+            let args = [
+                json!(name),
+                json!(true),
+                json!(true),
+                json!(""),
+                json!(false),
+                json!(true),
+                json!(true),
+            ];
+            client
+                .call::<Value>("createwallet", &args)
+                .map(|_| ())
+                .map_err(|e| WalletError::BitcoinError(format!("Failed to create wallet: {}", e)))
+        })
+        .await?;
+
+        let requests: Vec<ImportDescriptors> = chains
+            .iter()
+            .map(|chain| ImportDescriptors {
+                descriptor: chain.descriptor(),
+                timestamp,
+                active: Some(true),
+                internal: Some(chain.change),
+                ..Default::default()
+            })
+            .collect();
+
+        self.wallet_rpc(wallet_id, move |client| {
+            import_descriptors(client, &requests)
+        })
+        .await
+    }
+
+    /// Public ranged descriptors of the node wallet `wallet_id`, as the node
+    /// lists them, with its next index on each. Single-key descriptors have
+    /// no addresses to derive and are left out.
+    pub async fn descriptor_chains(&self, wallet_id: &str) -> WalletResult<Vec<DescriptorChain>> {
+        let wallet_id = parse_wallet_id(wallet_id)?;
 
-        match rpc_client.create_wallet(&wallet_name, None, None, None, None) {
-            Ok(_) => (),
-            Err(e) => {
-                if !e.to_string().contains("Database already exists") {
-                    return Err(WalletError::BitcoinError(e.to_string()));
+        let id = wallet_id.clone();
+        let listed = self
+            .wallet_rpc(&wallet_id, move |client| {
+                client
+                    .call::<Value>("listdescriptors", &[])
+                    .map_err(|e| wallet_rpc_error(e, &id))
+            })
+            .await?;
+
+        let mut chains = Vec::new();
+        for entry in listed["descriptors"].as_array().into_iter().flatten() {
+            let Some(descriptor) = entry["desc"].as_str() else {
+                continue;
+            };
+            if descriptor.contains('*') {
+                let next_index = entry["next"].as_u64().unwrap_or(0) as u32;
+                for mut chain in DescriptorChain::parse(descriptor, self.network)? {
+                    chain.next_index = next_index;
+                    chains.push(chain);
                 }
             }
         }
+        Ok(chains)
+    }
 
-        let secret_key = SecretKey::new(&mut thread_rng());
-        let private_key = PrivateKey::new(secret_key, self.network);
-        let compressed_pub_key = CompressedPublicKey::from_private_key(&self.secp, &private_key)
-            .map_err(|e| WalletError::BitcoinError(e.to_string()))?;
+    /// Hands out the next unused receive address of `address_type` from the
+    /// node wallet `wallet_id`.
+    pub async fn new_address(
+        &self,
+        wallet_id: &str,
+        address_type: ScriptKind,
+    ) -> WalletResult<NewAddressResponse> {
+        let wallet_id = parse_wallet_id(wallet_id)?;
 
-        let address = bitcoin::Address::p2wpkh(&compressed_pub_key, self.network);
+        let id = wallet_id.clone();
+        let address = self
+            .wallet_rpc(&wallet_id, move |client| {
+                client
+                    .get_new_address(None, Some(address_type.address_type()))
+                    .map_err(|e| wallet_rpc_error(e, &id))
+            })
+            .await?;
 
-        Ok(KeyPair {
-            private_key: private_key.to_string(),
-            public_key: compressed_pub_key.to_string(),
-            address: address.to_string(),
+        Ok(NewAddressResponse {
+            wallet_id,
+            address: address
+                .require_network(self.network)
+                .map_err(|e| {
+                    WalletError::BitcoinError(format!("Node returned a foreign address: {}", e))
+                })?
+                .to_string(),
+            address_type,
         })
     }
+
+    /// Reserves the next change address of the node wallet `wallet_id`, on
+    /// the script type of its first change chain in `chains`.
+    ///
+    /// The node keeps the wallet's change index: it persists it, moves it
+    /// past every address it hands out and past addresses it sees used.
+    /// Addresses the chain backend reports as used anyway, e.g. from history
+    /// before a watch-only wallet was registered, are skipped: the window of
+    /// addresses from the chain's `next_index` on is looked up in one batch,
+    /// and the node's index is then moved to the first unused one.
+    pub async fn next_change_address(
+        &self,
+        wallet_id: &str,
+        chains: &[DescriptorChain],
+    ) -> WalletResult<Address> {
+        let wallet_id = parse_wallet_id(wallet_id)?;
+        let chain = chains.iter().find(|chain| chain.change).ok_or_else(|| {
+            WalletError::InvalidAddress(format!("Wallet {} has no change descriptor", wallet_id))
+        })?;
+        let window = (chain.next_index..)
+            .take(MAX_USED_CHANGE_SKIPS + 1)
+            .map(|index| chain.address(&self.secp, index, self.network))
+            .collect::<WalletResult<Vec<_>>>()?;
+
+        let (kind, wallet_id) = (chain.kind, &wallet_id);
+        pick_change_address(self.chain.as_ref(), &window, || async move {
+            let id = wallet_id.clone();
+            self.wallet_rpc(wallet_id, move |client| {
+                client
+                    .get_raw_change_address(Some(kind.address_type()))
+                    .map_err(|e| wallet_rpc_error(e, &id))
+            })
+            .await?
+            .require_network(self.network)
+            .map_err(|e| {
+                WalletError::BitcoinError(format!("Node returned a foreign address: {}", e))
+            })
+        })
+        .await
+    }
+
+    /// Runs `f` against the node wallet `wallet_id` through its cached
+    /// client, loading the wallet first if the node has it on disk only. A
+    /// wallet the node does not know is dropped from the cache, so arbitrary
+    /// ids cannot make it grow.
+    async fn wallet_rpc<T, F>(&self, wallet_id: &str, f: F) -> WalletResult<T>
+    where
+        T: Send + 'static,
+        F: Fn(&RpcClient) -> WalletResult<T> + Send + Sync + 'static,
+    {
+        let f = Arc::new(f);
+        let client = self.wallet_client(wallet_id)?;
+
+        let call = |f: Arc<F>| blocking_rpc(&client, move |client| f(client));
+        let mut result = call(f.clone()).await;
+        if let Err(WalletError::WalletNotFound(_)) = result {
+            if self.load_wallet(wallet_id).await? {
+                result = call(f).await;
+            }
+        }
+        if let Err(WalletError::WalletNotFound(_)) = result {
+            self.wallets.lock().unwrap().remove(wallet_id);
+        }
+        result
+    }
+
+    /// Cached client for the node wallet `wallet_id`, built on first use.
+    fn wallet_client(&self, wallet_id: &str) -> WalletResult<Arc<RpcClient>> {
+        let mut wallets = self.wallets.lock().unwrap();
+        if let Some(client) = wallets.get(wallet_id) {
+            return Ok(client.clone());
+        }
+        let client = Arc::new(rpc_client(&self.rpc, Some(wallet_id))?);
+        wallets.insert(wallet_id.to_string(), client.clone());
+        Ok(client)
+    }
+
+    /// Loads the node wallet `wallet_id`, e.g. one created before the node
+    /// last restarted. Returns `false` if the node has no such wallet.
+    async fn load_wallet(&self, wallet_id: &str) -> WalletResult<bool> {
+        let name = wallet_id.to_string();
+        blocking_rpc(&self.node, move |client| match client.load_wallet(&name) {
+            Ok(_) => Ok(true),
+            Err(bitcoincore_rpc::Error::JsonRpc(jsonrpc::Error::Rpc(e))) => match e.code {
+                RPC_WALLET_NOT_FOUND => Ok(false),
+                RPC_WALLET_ALREADY_LOADED => Ok(true),
+                _ => Err(WalletError::BitcoinError(format!(
+                    "Failed to load wallet: {}",
+                    e.message
+                ))),
+            },
+            Err(e) => Err(WalletError::BitcoinError(format!(
+                "Failed to load wallet: {}",
+                e
+            ))),
+        })
+        .await
+    }
+}
+
+/// Picks the first address of `window`, the wallet's next change
+/// addresses in order, that the chain backend has never seen used, and
+/// calls `reserve`, which hands out the wallet's next change address,
+/// until it gets there.
+///
+/// The UTXOs of the whole window come from a single batch lookup, so a
+/// backend that scans the UTXO set does so once per call.
+async fn pick_change_address<F, Fut>(
+    chain: &dyn ChainBackend,
+    window: &[Address],
+    mut reserve: F,
+) -> WalletResult<Address>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = WalletResult<Address>>,
+{
+    let utxos = chain.get_utxos_batch(window).await?;
+    let mut unused = None;
+    for (position, (address, utxos)) in window.iter().zip(&utxos).enumerate() {
+        if utxos.is_empty() && !chain.has_history(address).await? {
+            unused = Some(position);
+            break;
+        }
+        tracing::info!("Skipping used change address {}", address);
+    }
+    let unused = unused.ok_or_else(|| {
+        WalletError::InvalidAddress(format!(
+            "No unused change address within {} of the node's index",
+            MAX_USED_CHANGE_SKIPS
+        ))
+    })?;
+
+    // The node hands out the window in order. Another send may have taken
+    // the pick in the meantime; any later address without UTXOs does too.
+    for _ in 0..window.len() {
+        let address = reserve().await?;
+        match window.iter().position(|candidate| *candidate == address) {
+            Some(position) if position < unused => continue,
+            Some(position) if utxos[position].is_empty() => return Ok(address),
+            Some(_) => continue,
+            None => break,
+        }
+    }
+    Err(WalletError::InvalidAddress(
+        "The node's change index moved past the checked addresses".to_string(),
+    ))
+}
+
+/// Wallet ids are UUIDs; anything else cannot name one of our node wallets.
+fn parse_wallet_id(wallet_id: &str) -> WalletResult<String> {
+    Uuid::parse_str(wallet_id)
+        .map(|id| id.to_string())
+        .map_err(|_| WalletError::WalletNotFound(wallet_id.to_string()))
+}
+
+/// Id of the watch-only wallet holding `descriptors`, the same for every
+/// registration of the same chains.
+fn watch_wallet_id(descriptors: &[String]) -> String {
+    let mut descriptors = descriptors.to_vec();
+    descriptors.sort();
+    let hash = sha256::Hash::hash(descriptors.join("\n").as_bytes());
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&hash[..16]);
+    Builder::from_custom_bytes(bytes).into_uuid().to_string()
+}
+
+fn wallet_rpc_error(e: bitcoincore_rpc::Error, wallet_id: &str) -> WalletError {
+    match e {
+        bitcoincore_rpc::Error::JsonRpc(jsonrpc::Error::Rpc(rpc_error))
+            if rpc_error.code == RPC_WALLET_NOT_FOUND =>
+        {
+            WalletError::WalletNotFound(wallet_id.to_string())
+        }
+        other => WalletError::BitcoinError(other.to_string()),
+    }
+}
+
+/// Imports `requests` into a watch-only node wallet.
+fn import_descriptors(client: &RpcClient, requests: &[ImportDescriptors]) -> WalletResult<()> {
+    let results = requests
+        .iter()
+        .map(|request| client.import_descriptors(request.clone()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| WalletError::BitcoinError(format!("Failed to import keys: {}", e)))?;
+    if let Some(error) = results
+        .into_iter()
+        .flatten()
+        .find_map(|result| result.error)
+    {
+        return Err(WalletError::BitcoinError(format!(
+            "Failed to import keys: {}",
+            error.message
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::{MempoolAcceptResult, Utxo};
+    use async_trait::async_trait;
+    use bitcoin::bip32::{Xpriv, Xpub};
+    use bitcoin::{Amount, FeeRate, Transaction};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Chain backend that only knows one address, as holding a UTXO, and
+    /// only answers batch lookups.
+    struct UsedAddress {
+        used: Address,
+        batches: AtomicUsize,
+    }
+
+    fn unsupported<T>() -> WalletResult<T> {
+        Err(WalletError::Unsupported("test backend".into()))
+    }
+
+    #[async_trait]
+    impl ChainBackend for UsedAddress {
+        fn name(&self) -> &'static str {
+            "test"
+        }
+
+        async fn get_utxos(&self, _address: &Address) -> WalletResult<Vec<Utxo>> {
+            unsupported()
+        }
+
+        async fn get_utxos_batch(&self, addresses: &[Address]) -> WalletResult<Vec<Vec<Utxo>>> {
+            self.batches.fetch_add(1, Ordering::SeqCst);
+            Ok(addresses
+                .iter()
+                .map(|address| match *address == self.used {
+                    true => vec![Utxo {
+                        outpoint: OutPoint::null(),
+                        value: Amount::from_sat(10_000),
+                        height: Some(1),
+                    }],
+                    false => Vec::new(),
+                })
+                .collect())
+        }
+
+        async fn get_transaction(&self, _txid: &Txid) -> WalletResult<Transaction> {
+            unsupported()
+        }
+
+        async fn get_tx_out(&self, _outpoint: &OutPoint) -> WalletResult<Option<TxOut>> {
+            unsupported()
+        }
+
+        async fn broadcast(&self, _tx: &Transaction) -> WalletResult<Txid> {
+            unsupported()
+        }
+
+        async fn test_mempool_accept(
+            &self,
+            _txs: &[Transaction],
+        ) -> WalletResult<Vec<MempoolAcceptResult>> {
+            unsupported()
+        }
+
+        async fn estimate_fee(&self, _conf_target: u16) -> WalletResult<Option<FeeRate>> {
+            unsupported()
+        }
+
+        async fn tip_height(&self) -> WalletResult<u32> {
+            unsupported()
+        }
+    }
+
+    #[tokio::test]
+    async fn change_address_skips_used_and_never_repeats() {
+        let network = Network::Regtest;
+        let secp = Secp256k1::new();
+        let master = Xpriv::new_master(network, &[7; 32]).unwrap();
+        let xpub = Xpub::from_priv(&secp, &master).to_string();
+        let chains = DescriptorChain::from_xpub(&xpub, ScriptKind::P2wpkh, None, network).unwrap();
+        let change = chains.iter().find(|chain| chain.change).unwrap();
+        let addresses: Vec<Address> = (0..4)
+            .map(|index| change.address(&secp, index, network).unwrap())
+            .collect();
+
+        let chain = UsedAddress {
+            used: addresses[0].clone(),
+            batches: AtomicUsize::new(0),
+        };
+        // Hands out the wallet's change addresses in order, like the node.
+        let mut node = addresses.clone().into_iter();
+        let mut reserve = || {
+            let address = node
+                .next()
+                .ok_or_else(|| WalletError::BitcoinError("No more change addresses".to_string()));
+            async move { address }
+        };
+
+        let first = pick_change_address(&chain, &addresses[0..3], &mut reserve)
+            .await
+            .unwrap();
+        let second = pick_change_address(&chain, &addresses[2..4], &mut reserve)
+            .await
+            .unwrap();
+
+        assert_eq!(first, addresses[1]);
+        assert_eq!(second, addresses[2]);
+        assert_eq!(chain.batches.load(Ordering::SeqCst), 2);
+    }
 }
@@ -1,7 +1,16 @@
 // api/src/services/mod.rs
 
+pub mod broadcast;
+pub mod coin_selection;
+pub mod commit;
 pub mod external;
+pub mod fees;
+pub mod hd;
 pub mod local;
+pub mod psbt;
+pub mod send;
 
 pub use external::ExternalWalletService;
+pub use fees::FeeService;
 pub use local::LocalWalletService;
+pub use send::SendService;
@@ -0,0 +1,207 @@
+// api/src/services/psbt.rs
+use crate::error::{WalletError, WalletResult};
+use crate::models::FinalizedTx;
+use bitcoin::{
+    consensus::encode::serialize_hex,
+    psbt::Psbt,
+    secp256k1::Secp256k1,
+    taproot::{ControlBlock, LeafVersion, TapLeafHash},
+    ScriptBuf, Transaction, TxOut, Txid, Witness, XOnlyPublicKey,
+};
+use std::{collections::BTreeMap, str::FromStr};
+
+/// Wraps the unsigned commit transaction, which spends the funding output.
+pub fn commit_psbt(commit_tx: &Transaction, funding: TxOut) -> WalletResult<Psbt> {
+    let mut psbt = unsigned_psbt(commit_tx)?;
+    for input in &mut psbt.inputs {
+        input.witness_utxo = Some(funding.clone());
+    }
+    Ok(psbt)
+}
+
+/// Wraps the spell transaction, attaching the spent outputs from `prev_txs`
+/// and `commit_tx`.
+///
+/// The input spending the commit output goes through the spell's tapscript
+/// leaf. Its witness, when present, carries the leaf and control block; they
+/// are checked against the commit output and recorded in the PSBT, and the
+/// witness itself is kept as the input's final witness.
+pub fn spell_psbt(
+    spell_tx: &Transaction,
+    commit_tx: &Transaction,
+    prev_txs: &BTreeMap<Txid, Transaction>,
+) -> WalletResult<Psbt> {
+    let commit_txid = commit_tx.compute_txid();
+    let mut psbt = unsigned_psbt(spell_tx)?;
+
+    for (index, (input, tx_in)) in psbt.inputs.iter_mut().zip(&spell_tx.input).enumerate() {
+        let prevout = tx_in.previous_output;
+        let spent = if prevout.txid == commit_txid {
+            Some(commit_tx)
+        } else {
+            prev_txs.get(&prevout.txid)
+        };
+        let witness_utxo = spent
+            .and_then(|tx| tx.output.get(prevout.vout as usize))
+            .cloned()
+            .ok_or_else(|| {
+                WalletError::InvalidTransaction(format!(
+                    "Missing previous output {} for input {}",
+                    prevout, index
+                ))
+            })?;
+
+        if prevout.txid == commit_txid && !tx_in.witness.is_empty() {
+            let (script, control_block) = tapscript_spend(&tx_in.witness)?;
+            let script_pubkey = &witness_utxo.script_pubkey;
+            let output_key = Some(script_pubkey)
+                .filter(|script_pubkey| script_pubkey.is_p2tr())
+                .and_then(|script_pubkey| {
+                    XOnlyPublicKey::from_slice(&script_pubkey.as_bytes()[2..]).ok()
+                })
+                .ok_or_else(|| {
+                    WalletError::InvalidTransaction("Commit output is not taproot".to_string())
+                })?;
+            if !control_block.verify_taproot_commitment(
+                &Secp256k1::verification_only(),
+                output_key,
+                &script,
+            ) {
+                return Err(WalletError::InvalidTransaction(
+                    "Spell leaf does not match the commit output".to_string(),
+                ));
+            }
+
+            input.tap_internal_key = Some(control_block.internal_key);
+            input
+                .tap_scripts
+                .insert(control_block.clone(), (script, control_block.leaf_version));
+            input.final_script_witness = Some(tx_in.witness.clone());
+        }
+        input.witness_utxo = Some(witness_utxo);
+    }
+
+    Ok(psbt)
+}
+
+/// Combines signed copies of each transaction, finalizes every input and
+/// extracts the transactions in the order they first appear.
+///
+/// Copies of the same unsigned transaction, e.g. signed by different
+/// parties, are merged before finalizing.
+pub fn finalize(psbts: &[String]) -> WalletResult<Vec<FinalizedTx>> {
+    if psbts.is_empty() {
+        return Err(WalletError::InvalidTransaction(
+            "At least one PSBT is required".to_string(),
+        ));
+    }
+
+    let mut combined: Vec<Psbt> = Vec::new();
+    for (index, encoded) in psbts.iter().enumerate() {
+        let psbt = Psbt::from_str(encoded.trim()).map_err(|e| {
+            WalletError::InvalidTransaction(format!("Invalid PSBT {}: {}", index, e))
+        })?;
+        let txid = psbt.unsigned_tx.compute_txid();
+        match combined
+            .iter_mut()
+            .find(|existing| existing.unsigned_tx.compute_txid() == txid)
+        {
+            Some(existing) => existing.combine(psbt).map_err(|e| {
+                WalletError::InvalidTransaction(format!("Cannot combine PSBT {}: {}", index, e))
+            })?,
+            None => combined.push(psbt),
+        }
+    }
+
+    combined
+        .into_iter()
+        .map(|mut psbt| {
+            finalize_inputs(&mut psbt)?;
+            let tx = psbt.extract_tx().map_err(|e| {
+                WalletError::InvalidTransaction(format!("Cannot extract transaction: {}", e))
+            })?;
+            Ok(FinalizedTx {
+                txid: tx.compute_txid().to_string(),
+                tx_hex: serialize_hex(&tx),
+            })
+        })
+        .collect()
+}
+
+/// Builds the final witness of every input that does not have one yet.
+///
+/// Handles the spends this wallet produces: P2WPKH, P2TR key path, and a
+/// P2TR script path through a leaf that needs a single signature.
+fn finalize_inputs(psbt: &mut Psbt) -> WalletResult<()> {
+    for (index, input) in psbt.inputs.iter_mut().enumerate() {
+        if input.final_script_witness.is_some() {
+            continue;
+        }
+
+        let witness = if let Some(signature) = input.tap_key_sig {
+            Witness::p2tr_key_spend(&signature)
+        } else if let Some((public_key, signature)) = input.partial_sigs.iter().next() {
+            Witness::p2wpkh(signature, &public_key.inner)
+        } else {
+            let leaf_spend =
+                input
+                    .tap_scripts
+                    .iter()
+                    .find_map(|(control_block, (script, version))| {
+                        let leaf_hash = TapLeafHash::from_script(script, *version);
+                        input
+                            .tap_script_sigs
+                            .iter()
+                            .find(|((_, hash), _)| *hash == leaf_hash)
+                            .map(|(_, signature)| (signature, script, control_block))
+                    });
+            let Some((signature, script, control_block)) = leaf_spend else {
+                return Err(WalletError::InvalidTransaction(format!(
+                    "Input {} of {} is not signed",
+                    index,
+                    psbt.unsigned_tx.compute_txid()
+                )));
+            };
+            let mut witness = Witness::new();
+            witness.push(signature.to_vec());
+            witness.push(script.as_bytes());
+            witness.push(control_block.serialize());
+            witness
+        };
+
+        input.final_script_witness = Some(witness);
+        input.partial_sigs.clear();
+        input.tap_key_sig = None;
+        input.tap_script_sigs.clear();
+        input.tap_scripts.clear();
+        input.bip32_derivation.clear();
+        input.tap_key_origins.clear();
+        input.tap_internal_key = None;
+        input.tap_merkle_root = None;
+    }
+    Ok(())
+}
+
+/// PSBT for `tx` with every script and witness stripped, as BIP174 requires.
+fn unsigned_psbt(tx: &Transaction) -> WalletResult<Psbt> {
+    let mut unsigned = tx.clone();
+    for input in &mut unsigned.input {
+        input.script_sig = ScriptBuf::new();
+        input.witness = Witness::new();
+    }
+    Psbt::from_unsigned_tx(unsigned)
+        .map_err(|e| WalletError::InvalidTransaction(format!("Failed to build PSBT: {}", e)))
+}
+
+/// Leaf script and control block of a script-path witness, which ends with
+/// `<script> <control block>`.
+pub fn tapscript_spend(witness: &Witness) -> WalletResult<(ScriptBuf, ControlBlock)> {
+    let invalid = || WalletError::InvalidTransaction("Malformed tapscript witness".to_string());
+    let control_block =
+        ControlBlock::decode(witness.last().ok_or_else(invalid)?).map_err(|_| invalid())?;
+    let leaf = witness.taproot_leaf_script().ok_or_else(invalid)?;
+    if leaf.version != LeafVersion::TapScript {
+        return Err(invalid());
+    }
+    Ok((leaf.script.into(), control_block))
+}
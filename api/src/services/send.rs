@@ -0,0 +1,340 @@
+// api/src/services/send.rs
+use crate::config::SendConfig;
+use crate::error::{WalletError, WalletResult};
+use crate::models::{ChangeOutput, TransactionRequest, TransactionResponse};
+use crate::network::parse_address;
+use crate::services::coin_selection::{select_coins, Candidate, SelectionAlgorithm};
+use crate::services::external::{ExternalWalletService, OwnedUtxo};
+use crate::services::fees::FeeService;
+use crate::services::hd::{DescriptorChain, ScriptKind};
+use crate::services::local::LocalWalletService;
+use bitcoin::{
+    absolute::LockTime, psbt::Psbt, transaction::Version, Address, Amount, FeeRate, Network,
+    ScriptBuf, Sequence, Transaction, TxIn, TxOut, Weight, Witness, XOnlyPublicKey,
+};
+use std::sync::Arc;
+
+/// Segwit marker and flag, counted once per transaction.
+pub const SEGWIT_HEADER_WU: u64 = 2;
+
+/// Outpoint, empty script length and sequence of an input, in weight units.
+pub const INPUT_BASE_WU: u64 = 41 * 4;
+
+/// Where leftover value goes. A wallet's change address is only reserved
+/// once the transaction turns out to need one.
+enum ChangeDestination {
+    Address(Address),
+    Wallet(String),
+}
+
+/// Builds unsigned BTC sends as PSBTs for client-side signing.
+pub struct SendService {
+    network: Network,
+    external: Arc<ExternalWalletService>,
+    local: Arc<LocalWalletService>,
+    fees: Arc<FeeService>,
+    config: SendConfig,
+}
+
+impl SendService {
+    pub fn new(
+        network: Network,
+        external: Arc<ExternalWalletService>,
+        local: Arc<LocalWalletService>,
+        fees: Arc<FeeService>,
+        config: SendConfig,
+    ) -> Self {
+        Self {
+            network,
+            external,
+            local,
+            fees,
+            config,
+        }
+    }
+
+    /// Selects coins for `request` and returns the unsigned PSBT.
+    ///
+    /// `chains` are the descriptor chains of `request.wallet_id`; they are
+    /// ignored when spending from a single address. Outputs carrying charms
+    /// are never selected, so a plain send cannot burn them.
+    pub async fn build_psbt(
+        &self,
+        request: &TransactionRequest,
+        chains: &[DescriptorChain],
+    ) -> WalletResult<TransactionResponse> {
+        let recipient = parse_address(&request.to_address, self.network)?;
+        let recipient_script = recipient.script_pubkey();
+        let dust = recipient_script.minimal_non_dust();
+        if request.amount < dust {
+            return Err(WalletError::InvalidAmount(format!(
+                "Amount of {} sats is below the dust limit of {} sats",
+                request.amount.to_sat(),
+                dust.to_sat()
+            )));
+        }
+        let fee_rate = self
+            .fees
+            .requested_fee_rate(request.fee_rate_sat_vb, request.conf_target)
+            .await?;
+
+        let (owned, default_change) = match (&request.wallet_id, &request.from_address) {
+            (Some(wallet_id), None) => (
+                self.external.owned_utxos(wallet_id, chains).await?,
+                ChangeDestination::Wallet(wallet_id.clone()),
+            ),
+            (None, Some(address)) => {
+                let address = parse_address(address, self.network)?;
+                (
+                    self.external.owned_address_utxos(&address).await?,
+                    ChangeDestination::Address(address),
+                )
+            }
+            _ => {
+                return Err(WalletError::InvalidAddress(
+                    "Provide exactly one of wallet_id or from_address".to_string(),
+                ))
+            }
+        };
+
+        let change_destination = match &request.change_address {
+            Some(address) => ChangeDestination::Address(parse_address(address, self.network)?),
+            None => default_change,
+        };
+        let change_kind = match &change_destination {
+            ChangeDestination::Address(address) => ScriptKind::of(address).ok_or_else(|| {
+                WalletError::InvalidAddress(
+                    "Change must go to a P2WPKH or P2TR address".to_string(),
+                )
+            })?,
+            ChangeDestination::Wallet(wallet_id) => chains
+                .iter()
+                .find(|chain| chain.change)
+                .map(|chain| chain.kind)
+                .ok_or_else(|| {
+                    WalletError::InvalidAddress(format!(
+                        "Wallet {} has no change descriptor",
+                        wallet_id
+                    ))
+                })?,
+        };
+
+        let excluded_charm_utxos = owned.iter().filter(|owned| owned.has_charms).count();
+        let spendable: Vec<(&OwnedUtxo, ScriptKind)> = owned
+            .iter()
+            .filter(|owned| !owned.has_charms)
+            .filter(|owned| self.config.spend_unconfirmed || owned.utxo.is_confirmed())
+            .filter_map(|owned| owned.kind.map(|kind| (owned, kind)))
+            .collect();
+        let candidates: Vec<Candidate> = spendable
+            .iter()
+            .map(|(owned, kind)| Candidate {
+                value: owned.utxo.value,
+                input_weight: input_weight(*kind),
+            })
+            .collect();
+
+        let mut tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: Vec::new(),
+            output: vec![TxOut {
+                value: request.amount,
+                script_pubkey: recipient_script,
+            }],
+        };
+        let fixed_weight = tx.weight() + Weight::from_wu(SEGWIT_HEADER_WU);
+        let (change_weight, change_dust) = change_output(change_kind);
+        let change_fee = fee_for(fee_rate, change_weight)?;
+        let min_change = change_dust.max(Amount::from_sat(self.config.min_change_sat));
+
+        let selection = select_coins(
+            &candidates,
+            request.amount + fee_for(fee_rate, fixed_weight)?,
+            fee_rate,
+            change_fee + fee_for(fee_rate, input_weight(change_kind))?,
+            change_fee + min_change,
+        )?;
+        let selected: Vec<(&OwnedUtxo, ScriptKind)> =
+            selection.inputs.iter().map(|&i| spendable[i]).collect();
+
+        let input_total: Amount = selected.iter().map(|(owned, _)| owned.utxo.value).sum();
+        let inputs_weight = selected.iter().fold(Weight::ZERO, |weight, (_, kind)| {
+            weight + input_weight(*kind)
+        });
+        let base_fee = fee_for(fee_rate, fixed_weight + inputs_weight)?;
+        let excess = input_total
+            .checked_sub(request.amount + base_fee)
+            .ok_or_else(|| {
+                WalletError::InsufficientFunds(format!(
+                    "selected inputs cover {} sats, {} sats needed",
+                    input_total.to_sat(),
+                    (request.amount + base_fee).to_sat()
+                ))
+            })?;
+
+        let (fee, change) = if let Some(value) =
+            change_value(selection.algorithm, excess, change_fee, min_change)
+        {
+            let address = match &change_destination {
+                ChangeDestination::Address(address) => address.clone(),
+                ChangeDestination::Wallet(wallet_id) => {
+                    self.local.next_change_address(wallet_id, chains).await?
+                }
+            };
+            tx.output.push(TxOut {
+                value,
+                script_pubkey: address.script_pubkey(),
+            });
+            (
+                base_fee + change_fee,
+                Some(ChangeOutput {
+                    address: address.to_string(),
+                    value,
+                }),
+            )
+        } else {
+            (base_fee + excess, None)
+        };
+
+        tx.input = selected
+            .iter()
+            .map(|(owned, _)| TxIn {
+                previous_output: owned.utxo.outpoint,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            })
+            .collect();
+
+        let mut psbt = Psbt::from_unsigned_tx(tx)
+            .map_err(|e| WalletError::InvalidTransaction(format!("Failed to build PSBT: {}", e)))?;
+        for (input, (owned, kind)) in psbt.inputs.iter_mut().zip(&selected) {
+            input.witness_utxo = Some(owned.txout.clone());
+            let Some((public_key, source)) = &owned.key_source else {
+                continue;
+            };
+            match kind {
+                ScriptKind::P2wpkh => {
+                    input.bip32_derivation.insert(*public_key, source.clone());
+                }
+                ScriptKind::P2tr => {
+                    let internal_key = XOnlyPublicKey::from(*public_key);
+                    input.tap_internal_key = Some(internal_key);
+                    input
+                        .tap_key_origins
+                        .insert(internal_key, (Vec::new(), source.clone()));
+                }
+            }
+        }
+
+        tracing::info!(
+            "Built PSBT spending {} inputs with {:?}, fee {} sats",
+            selected.len(),
+            selection.algorithm,
+            fee.to_sat()
+        );
+
+        Ok(TransactionResponse {
+            tx_id: psbt.unsigned_tx.compute_txid().to_string(),
+            psbt: psbt.to_string(),
+            fee,
+            fee_rate_sat_vb: fee_rate.to_sat_per_vb_ceil(),
+            algorithm: selection.algorithm,
+            inputs: selected
+                .iter()
+                .map(|(owned, _)| owned.utxo.outpoint.to_string())
+                .collect(),
+            change,
+            excluded_charm_utxos,
+        })
+    }
+}
+
+/// Weight an input of `kind` adds once signed: an ECDSA signature and public
+/// key for P2WPKH, one Schnorr signature for a P2TR key-path spend.
+pub fn input_weight(kind: ScriptKind) -> Weight {
+    let witness = match kind {
+        ScriptKind::P2wpkh => 1 + (1 + 72) + (1 + 33),
+        ScriptKind::P2tr => 1 + (1 + 64),
+    };
+    Weight::from_wu(INPUT_BASE_WU + witness)
+}
+
+/// Value of the change output left from `excess`, or `None` if all of it
+/// goes to the fee.
+///
+/// Branch-and-bound selections are changeless: their overshoot may reach
+/// the cost of creating and later spending change, which is more than the
+/// smallest change worth adding, and is paid as fee instead.
+fn change_value(
+    algorithm: SelectionAlgorithm,
+    excess: Amount,
+    change_fee: Amount,
+    min_change: Amount,
+) -> Option<Amount> {
+    (algorithm == SelectionAlgorithm::Knapsack && excess >= change_fee + min_change)
+        .then(|| excess - change_fee)
+}
+
+/// Weight and dust limit of a change output of `kind`.
+fn change_output(kind: ScriptKind) -> (Weight, Amount) {
+    let script_len: u64 = match kind {
+        ScriptKind::P2wpkh => 22,
+        ScriptKind::P2tr => 34,
+    };
+    let size = 8 + 1 + script_len;
+    // Bitcoin Core's dust threshold for witness outputs at the default
+    // 3 sat/vB dust relay fee: the output plus 67 vbytes to spend it.
+    (Weight::from_wu(size * 4), Amount::from_sat((size + 67) * 3))
+}
+
+pub fn fee_for(fee_rate: FeeRate, weight: Weight) -> WalletResult<Amount> {
+    fee_rate
+        .fee_wu(weight)
+        .ok_or_else(|| WalletError::InvalidAmount("Fee overflows".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn branch_and_bound_overshoot_adds_no_change() {
+        // At 50 sat/vB a P2WPKH change output costs 1_550 sats and spending
+        // it later 3_413, so branch-and-bound accepts overshoots far above
+        // the 2_550 sats that would otherwise be worth a change output.
+        let fee_rate = FeeRate::from_sat_per_vb(50).unwrap();
+        let (change_weight, change_dust) = change_output(ScriptKind::P2wpkh);
+        let change_fee = fee_for(fee_rate, change_weight).unwrap();
+        let min_change = change_dust.max(Amount::from_sat(1_000));
+        let spend_fee = fee_for(fee_rate, input_weight(ScriptKind::P2wpkh)).unwrap();
+
+        let target = Amount::from_sat(100_000);
+        let overshoot = Amount::from_sat(3_000);
+        let candidates = [Candidate {
+            value: target + overshoot + spend_fee,
+            input_weight: input_weight(ScriptKind::P2wpkh),
+        }];
+        let selection = select_coins(
+            &candidates,
+            target,
+            fee_rate,
+            change_fee + spend_fee,
+            change_fee + min_change,
+        )
+        .unwrap();
+        assert_eq!(selection.algorithm, SelectionAlgorithm::BranchAndBound);
+
+        let excess = candidates[0].value - spend_fee - target;
+        assert!(excess >= change_fee + min_change);
+        assert_eq!(
+            change_value(selection.algorithm, excess, change_fee, min_change),
+            None
+        );
+        assert_eq!(
+            change_value(SelectionAlgorithm::Knapsack, excess, change_fee, min_change),
+            Some(excess - change_fee)
+        );
+    }
+}
@@ -0,0 +1,82 @@
+// api/src/state/mod.rs
+use crate::chain::{rpc_client, ChainBackend, CoreRpcBackend, EsploraBackend};
+use crate::config::{BackendKind, Config};
+use crate::error::WalletResult;
+use crate::services::hd::DescriptorChain;
+use crate::services::{ExternalWalletService, FeeService, LocalWalletService, SendService};
+use axum::extract::FromRef;
+use std::sync::Arc;
+
+/// Long-lived clients and services shared by every handler.
+///
+/// Built once in `main`, so connections are reused across requests and
+/// handlers never construct clients themselves.
+#[derive(Clone)]
+pub struct AppState {
+    pub config: Arc<Config>,
+    pub chain: Arc<dyn ChainBackend>,
+    pub external: Arc<ExternalWalletService>,
+    pub fees: Arc<FeeService>,
+    pub local: Arc<LocalWalletService>,
+    pub send: Arc<SendService>,
+}
+
+impl AppState {
+    pub fn new(config: Config) -> WalletResult<Self> {
+        let config = Arc::new(config);
+        let rpc = Arc::new(rpc_client(&config.rpc, None)?);
+
+        let chain: Arc<dyn ChainBackend> = match config.chain.backend {
+            BackendKind::Core => Arc::new(CoreRpcBackend::new(rpc.clone())),
+            BackendKind::Esplora => {
+                // Config::load guarantees a URL whenever Esplora is selected.
+                let url = config.chain.esplora_url.clone().unwrap_or_default();
+                Arc::new(EsploraBackend::new(reqwest::Client::new(), url))
+            }
+        };
+
+        let external = Arc::new(ExternalWalletService::new(
+            config.network,
+            chain.clone(),
+            config.watch.gap_limit,
+        ));
+        let fees = Arc::new(FeeService::new(chain.clone(), config.fees.clone()));
+        let local = Arc::new(LocalWalletService::new(
+            config.rpc.clone(),
+            rpc.clone(),
+            chain.clone(),
+            config.network,
+        ));
+
+        let send = Arc::new(SendService::new(
+            config.network,
+            external.clone(),
+            local.clone(),
+            fees.clone(),
+            config.send.clone(),
+        ));
+
+        Ok(Self {
+            config,
+            chain,
+            external,
+            fees,
+            local,
+            send,
+        })
+    }
+}
+
+impl AppState {
+    /// Descriptor chains of a wallet, whether created, imported or
+    /// registered as watch-only; the node holds all of them.
+    pub async fn wallet_chains(&self, wallet_id: &str) -> WalletResult<Vec<DescriptorChain>> {
+        self.local.descriptor_chains(wallet_id).await
+    }
+}
+
+impl FromRef<AppState> for Arc<Config> {
+    fn from_ref(state: &AppState) -> Self {
+        state.config.clone()
+    }
+}
@@ -1,6 +1,10 @@
 import { WALLET_API_URL } from '../shared/constants';
 
-import type { TransferCharmsResponse } from '../../types';
+import type {
+    ChangeDestination,
+    TransferCharmsRequest,
+    TransferCharmsResponse
+} from '../../types';
 
 export class TransferCharmsService {
     private readonly API_URL = `${WALLET_API_URL}/wallet/prove_spell`;
@@ -9,7 +13,8 @@ export class TransferCharmsService {
         recipient: string,
         amount: number,
         spellJson: string,
-        fundingUtxoId: string
+        fundingUtxoId: string,
+        change: ChangeDestination
     ): Promise<TransferCharmsResponse> {
         // Validate inputs
         if (!spellJson?.trim()) {
@@ -21,6 +26,16 @@ export class TransferCharmsService {
         if (!fundingUtxoId?.trim()) {
             throw new Error('Funding UTXO ID is required');
         }
+        if ('wallet_id' in change ? !change.wallet_id?.trim() : !change.change_address?.trim()) {
+            throw new Error('A wallet id or change address is required');
+        }
+
+        const request: TransferCharmsRequest = {
+            spell_json: spellJson,
+            funding_utxo_id: fundingUtxoId,
+            destination_address: recipient,
+            ...change
+        };
 
         try {
             // Log the full request for debugging
@@ -28,6 +43,7 @@ export class TransferCharmsService {
                 recipient,
                 amount,
                 fundingUtxoId,
+                change,
                 spellJson // Log full spell for debugging
             });
 
@@ -45,11 +61,7 @@ export class TransferCharmsService {
             const response = await fetch(this.API_URL, {
                 method: 'POST',
                 headers: { 'Content-Type': 'application/json' },
-                body: JSON.stringify({
-                    spell_json: spellJson,
-                    funding_utxo_id: fundingUtxoId,
-                    destination_address: recipient
-                })
+                body: JSON.stringify(request)
             });
 
             // Log the full response for debugging
@@ -72,11 +84,7 @@ export class TransferCharmsService {
                 statusText: error.response?.statusText,
                 data: error.response?.data,
                 message: error.message,
-                requestData: {
-                    spell_json: spellJson,
-                    funding_utxo_id: fundingUtxoId,
-                    destination_address: recipient
-                }
+                requestData: request
             };
             console.error('Transfer request failed:', errorDetails);
 
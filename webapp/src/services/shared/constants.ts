@@ -4,6 +4,10 @@ export const CHARMS_API_URL = import.meta.env.VITE_CHARMS_API_URL || 'http://loc
 
 // Bitcoin network constants
 export const NETWORK = 'testnet';
+export const BECH32_HRP = 'tb';
+// BIP44 coin type and tprv/tpub version bytes of test networks
+export const COIN_TYPE = 1;
+export const BIP32_VERSIONS = { private: 0x04358394, public: 0x043587cf };
 export const EXPLORER_URL = 'https://mempool.space/testnet4';
 
 // Elliptic curve parameters
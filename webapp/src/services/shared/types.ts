@@ -6,8 +6,13 @@ export interface Wallet {
 
 export interface BalanceResponse {
     address: string;
+    // Satoshis
     balance: number;
     unconfirmed_balance: number;
+    display?: {
+        balance: string;
+        unconfirmed_balance: string;
+    };
 }
 
 export interface SignedTransaction {
@@ -1,12 +1,31 @@
 import axios from 'axios';
+import { bech32 } from 'bech32';
 import { ec as EC } from 'elliptic';
-import { WALLET_API_URL, CURVE_NAME } from '../shared/constants';
+import { HDKey } from '@scure/bip32';
+import { generateMnemonic, mnemonicToSeedSync } from '@scure/bip39';
+import { wordlist } from '@scure/bip39/wordlists/english';
+import { sha256 } from '@noble/hashes/sha256';
+import { ripemd160 } from '@noble/hashes/ripemd160';
+import { hexToBytes } from '@noble/hashes/utils';
+import { WALLET_API_URL, CURVE_NAME, BECH32_HRP, BIP32_VERSIONS, COIN_TYPE } from '../shared/constants';
 import { WalletImportError } from '../../types';
-import type { Wallet, BalanceResponse, UTXO } from '../../types';
+import type {
+    Wallet,
+    BalanceResponse,
+    UTXO,
+    AccountKey,
+    CreateWalletResponse
+} from '../../types';
 
 const ec = new EC(CURVE_NAME);
 
-function arrayToHex(array: number[]): string {
+// Accounts registered with the API: BIP84 native segwit and BIP86 taproot
+const ACCOUNTS: Array<{ script_type: AccountKey['script_type']; purpose: number }> = [
+    { script_type: 'p2wpkh', purpose: 84 },
+    { script_type: 'p2tr', purpose: 86 }
+];
+
+function arrayToHex(array: ArrayLike<number>): string {
     return Array.from(array)
         .map(b => b.toString(16).padStart(2, '0'))
         .join('');
@@ -56,57 +75,62 @@ function privateKeyFromWIF(wif: string): string {
     return arrayToHex(privateKeyBytes);
 }
 
-// Simple deterministic address derivation based on public key
-function deriveAddress(publicKey: string): string {
-    // Using first 20 bytes of public key to generate a unique pattern
-    const pubKeyBytes = new Uint8Array(
-        publicKey.slice(0, 40).match(/.{1,2}/g)?.map(byte => parseInt(byte, 16)) || []
-    );
-
-    // Create a simple deterministic pattern for testnet address
-    let addressChars = '';
-    for (let i = 0; i < 32; i++) {
-        const idx = pubKeyBytes[i % pubKeyBytes.length] % 36;
-        addressChars += '0123456789abcdefghijklmnopqrstuvwxyz'[idx];
-    }
-
-    // Return testnet format address
-    return `tb1q${addressChars}`;
+// P2WPKH address paying to a compressed public key
+function p2wpkhAddress(publicKey: Uint8Array): string {
+    const hash = ripemd160(sha256(publicKey));
+    return bech32.encode(BECH32_HRP, [0, ...bech32.toWords(hash)]);
 }
 
 class WalletApi {
     private readonly MEMPOOL_API = 'https://mempool.space/testnet4/api';
 
-    async createWallet(password: string): Promise<Wallet> {
-        try {
-            // First approach: Try using the API
-            const response = await axios.post(`${WALLET_API_URL}/wallet/create`, { password });
-            return response.data;
-        } catch (error) {
-            console.log("API wallet creation failed, falling back to local generation");
-
-            // Second approach: Generate locally using elliptic
-            const keyPair = ec.genKeyPair();
-            const privateKey = keyPair.getPrivate('hex');
-            const publicKey = keyPair.getPublic(true, 'hex');
-            const address = deriveAddress(publicKey);
-
-            // Create wallet object
-            const wallet: Wallet = {
-                private_key: privateKey,
-                public_key: publicKey,
-                address: address
+    // Returns the wallet and its BIP39 phrase, which the caller must keep
+    // encrypted; neither the phrase nor the seed leaves the browser
+    async createWallet(): Promise<{ wallet: Wallet; mnemonic: string }> {
+        // The seed stays here; the API only gets the account xpubs
+        const mnemonic = generateMnemonic(wordlist, 128);
+        const master = HDKey.fromMasterSeed(mnemonicToSeedSync(mnemonic), BIP32_VERSIONS);
+        const fingerprint = master.fingerprint.toString(16).padStart(8, '0');
+
+        const accounts: AccountKey[] = ACCOUNTS.map(({ script_type, purpose }) => {
+            const path = `${purpose}'/${COIN_TYPE}'/0'`;
+            return {
+                script_type,
+                xpub: master.derive(`m/${path}`).publicExtendedKey,
+                origin: `${fingerprint}/${path}`
             };
+        });
+
+        const response = await axios.post<CreateWalletResponse>(`${WALLET_API_URL}/wallet/create`, {
+            accounts,
+            address_type: 'p2wpkh'
+        });
+        const created = response.data;
+
+        // The first BIP84 receive key signs for the address the API returned
+        const receiveKey = master.derive(`m/84'/${COIN_TYPE}'/0'/0/0`);
+        if (!receiveKey.privateKey || !receiveKey.publicKey) {
+            throw new Error('Failed to derive the receive key');
+        }
+        if (p2wpkhAddress(receiveKey.publicKey) !== created.address) {
+            throw new Error('The API returned an address this wallet does not own');
+        }
 
-            // Store encrypted wallet
-            try {
-                localStorage.setItem('bitcoin_wallet', JSON.stringify(wallet));
-            } catch (storageError) {
-                console.warn('Failed to store wallet in localStorage');
-            }
+        const wallet: Wallet = {
+            private_key: arrayToHex(receiveKey.privateKey),
+            public_key: arrayToHex(receiveKey.publicKey),
+            address: created.address,
+            wallet_id: created.wallet_id
+        };
 
-            return wallet;
+        // Store encrypted wallet
+        try {
+            localStorage.setItem('bitcoin_wallet', JSON.stringify(wallet));
+        } catch (storageError) {
+            console.warn('Failed to store wallet in localStorage');
         }
+
+        return { wallet, mnemonic };
     }
 
     async getBalance(address: string): Promise<BalanceResponse> {
@@ -123,9 +147,7 @@ class WalletApi {
             const privateKeyHex = privateKeyFromWIF(wifPrivateKey);
             const keyPair = ec.keyFromPrivate(privateKeyHex);
             const publicKey = keyPair.getPublic(true, 'hex');
-
-            // Generate address from public key
-            const address = deriveAddress(publicKey);
+            const address = p2wpkhAddress(hexToBytes(publicKey));
 
             const wallet: Wallet = {
                 public_key: publicKey,
@@ -37,6 +37,20 @@ export interface CommitTxInfo {
     amount: number;
 }
 
+// Where /wallet/prove_spell sends leftover funding: the next unused change
+// address of an API wallet, or an address the user's wallet owns
+export type ChangeDestination =
+    | { wallet_id: string }
+    | { change_address: string };
+
+export type TransferCharmsRequest = {
+    spell_json: string;
+    funding_utxo_id: string;
+    destination_address: string;
+    fee_rate_sat_vb?: number;
+    conf_target?: number;
+} & ChangeDestination;
+
 export interface TransferCharmsResponse {
     transactions: {
         commit_tx: string;
@@ -75,6 +75,16 @@ export interface TransferCharmsResponse {
     transactions: {
         commit_tx: string;
         spell_tx: string;
-        taproot_data: TaprootData;
+        taproot_data: TaprootData | null;
+    };
+    // BIP174 PSBTs (base64) for signing; finalize them with /wallet/finalize
+    psbts: {
+        commit_tx: string;
+        spell_tx: string;
+    };
+    // Input indices the user must sign; the spell tx's commit input is signed by the API
+    inputs_to_sign: {
+        commit_tx: number[];
+        spell_tx: number[];
     };
 }
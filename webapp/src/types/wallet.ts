@@ -2,12 +2,41 @@ export interface Wallet {
     public_key: string;
     private_key: string;
     address: string;
+    // Id of the API wallet holding this wallet's descriptors, if registered
+    wallet_id?: string;
+}
+
+export type ScriptType = 'p2wpkh' | 'p2tr';
+
+// Account xpub registered with /wallet/create
+export interface AccountKey {
+    script_type: ScriptType;
+    xpub: string;
+    // Master key fingerprint and hardened path, e.g. d34db33f/84'/1'/0'
+    origin: string;
+}
+
+export interface CreateWalletResponse {
+    wallet_id: string;
+    address: string;
+    address_type: ScriptType;
+    accounts: Array<{
+        script_type: ScriptType;
+        receive_descriptor: string;
+        change_descriptor: string;
+        address: string;
+    }>;
 }
 
 export interface BalanceResponse {
     address: string;
+    // Satoshis
     balance: number;
     unconfirmed_balance: number;
+    display?: {
+        balance: string;
+        unconfirmed_balance: string;
+    };
 }
 
 export class WalletImportError extends Error {